@@ -0,0 +1,35 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Embed the SQL scripts of the migrations folder into the binary, see postgres::migration
+fn main() {
+    let manifest_directory = env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set");
+    let migrations_folder = Path::new(&manifest_directory).join("migrations");
+    println!("cargo:rerun-if-changed={}", migrations_folder.display());
+    let mut paths: Vec<PathBuf> = fs::read_dir(&migrations_folder)
+        .expect("Failed to read migrations folder")
+        .map(|entry| {
+            entry
+                .expect("Failed to read migrations folder entry")
+                .path()
+        })
+        .filter(|path| {
+            path.is_file() && path.extension().is_some_and(|extension| extension == "sql")
+        })
+        .collect();
+    paths.sort();
+    let mut generated = String::from("pub static EMBEDDED_MIGRATIONS: &[(&str, &str)] = &[\n");
+    for path in paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+        generated.push_str(&format!(
+            "    ({:?}, include_str!({:?})),\n",
+            path.file_name().unwrap().to_string_lossy(),
+            path.display().to_string()
+        ));
+    }
+    generated.push_str("];\n");
+    let output =
+        Path::new(&env::var("OUT_DIR").expect("OUT_DIR is set")).join("embedded_migrations.rs");
+    fs::write(output, generated).expect("Failed to write embedded migrations");
+}
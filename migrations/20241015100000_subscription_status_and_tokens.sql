@@ -0,0 +1,26 @@
+BEGIN;
+
+-- Track double opt-in state of subscribers. Rows inserted before the
+-- confirmation flow existed are considered confirmed
+ALTER TABLE newsletter.subscription
+  ADD COLUMN IF NOT EXISTS status TEXT NULL;
+
+UPDATE newsletter.subscription
+  SET status = 'confirmed'
+  WHERE status IS NULL;
+
+ALTER TABLE newsletter.subscription
+  ALTER COLUMN status SET NOT NULL,
+  ALTER COLUMN status SET DEFAULT 'pending_confirmation',
+  ADD CONSTRAINT subscription_status_check
+    CHECK (status IN ('pending_confirmation', 'confirmed'));
+
+-- Confirmation tokens sent by email on subscription
+CREATE TABLE IF NOT EXISTS newsletter.subscription_tokens(
+    subscription_token TEXT NOT NULL,
+    PRIMARY KEY (subscription_token),
+    subscriber_id uuid NOT NULL REFERENCES newsletter.subscription (id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+COMMIT;
@@ -0,0 +1,19 @@
+BEGIN;
+
+-- Published newsletter issues and their delivery progress
+CREATE TABLE IF NOT EXISTS newsletter.newsletter_issues(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    title TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'in_progress',
+    total_recipients INTEGER NOT NULL DEFAULT 0,
+    delivered INTEGER NOT NULL DEFAULT 0,
+    failed INTEGER NOT NULL DEFAULT 0,
+    published_at timestamptz NOT NULL DEFAULT now(),
+    completed_at timestamptz NULL,
+    CONSTRAINT newsletter_issues_status_check CHECK (status IN ('in_progress', 'completed'))
+);
+
+COMMIT;
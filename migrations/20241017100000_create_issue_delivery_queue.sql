@@ -0,0 +1,26 @@
+BEGIN;
+
+-- Transactional outbox of pending newsletter deliveries, one row per issue and recipient
+CREATE TABLE IF NOT EXISTS newsletter.issue_delivery_queue(
+    issue_id uuid NOT NULL REFERENCES newsletter.newsletter_issues (id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES newsletter.subscription (id) ON DELETE CASCADE,
+    PRIMARY KEY (issue_id, subscriber_id),
+    n_retries INTEGER NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    last_error TEXT NULL
+);
+
+CREATE INDEX IF NOT EXISTS issue_delivery_queue_execute_after_idx
+    ON newsletter.issue_delivery_queue (execute_after);
+
+-- Deliveries abandoned after exhausting their retries
+CREATE TABLE IF NOT EXISTS newsletter.issue_delivery_failures(
+    issue_id uuid NOT NULL REFERENCES newsletter.newsletter_issues (id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES newsletter.subscription (id) ON DELETE CASCADE,
+    PRIMARY KEY (issue_id, subscriber_id),
+    n_retries INTEGER NOT NULL,
+    last_error TEXT NOT NULL,
+    failed_at timestamptz NOT NULL DEFAULT now()
+);
+
+COMMIT;
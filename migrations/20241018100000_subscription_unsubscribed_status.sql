@@ -0,0 +1,11 @@
+BEGIN;
+
+-- Subscribers leaving the list are kept with an 'unsubscribed' status
+-- instead of being deleted, preserving delivery history
+ALTER TABLE newsletter.subscription
+  DROP CONSTRAINT IF EXISTS subscription_status_check,
+  ADD CONSTRAINT subscription_status_check
+    CHECK (status IN ('pending_confirmation', 'confirmed', 'unsubscribed')),
+  ADD COLUMN IF NOT EXISTS unsubscribed_at timestamptz NULL;
+
+COMMIT;
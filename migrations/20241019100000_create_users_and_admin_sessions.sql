@@ -0,0 +1,24 @@
+BEGIN;
+
+-- Administrators of the newsletter, passwords stored as Argon2id PHC strings
+CREATE TABLE IF NOT EXISTS newsletter.users(
+    user_id uuid NOT NULL,
+    PRIMARY KEY (user_id),
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- Sessions issued by the admin login, referenced by signed cookies
+CREATE TABLE IF NOT EXISTS newsletter.admin_sessions(
+    session_id TEXT NOT NULL,
+    PRIMARY KEY (session_id),
+    user_id uuid NOT NULL REFERENCES newsletter.users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS admin_sessions_expires_at_idx
+    ON newsletter.admin_sessions (expires_at);
+
+COMMIT;
@@ -0,0 +1,21 @@
+BEGIN;
+
+-- Keys of machine clients of the admin server. Only a SHA-256 digest of each
+-- key is stored, the key itself is shown once at creation
+CREATE TABLE IF NOT EXISTS newsletter.api_keys(
+    key_id uuid NOT NULL,
+    PRIMARY KEY (key_id),
+    name TEXT NOT NULL,
+    key_prefix TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_by uuid NULL REFERENCES newsletter.users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL,
+    CONSTRAINT api_keys_scopes_check CHECK (
+        scopes <@ ARRAY['subscribers:read', 'newsletters:read', 'newsletters:send']::TEXT[]
+    )
+);
+
+COMMIT;
@@ -0,0 +1,303 @@
+use crate::authentication::AuthenticatedAdmin;
+use actix_web::dev::Payload;
+use actix_web::http::header::{HeaderMap, AUTHORIZATION};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use deadpool_postgres::{Object, Pool};
+use futures::future::LocalBoxFuture;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use uuid::{NoContext, Timestamp, Uuid};
+
+pub static API_KEY_PREFIX: &str = "nl_";
+pub static API_KEY_SECRET_LENGTH: usize = 40;
+// Characters of the key kept in clear to tell keys apart in listings
+pub static API_KEY_DISPLAY_PREFIX_LENGTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApiKeyScope {
+    SubscribersRead,
+    NewslettersRead,
+    NewslettersSend,
+}
+
+impl ApiKeyScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::SubscribersRead => "subscribers:read",
+            ApiKeyScope::NewslettersRead => "newsletters:read",
+            ApiKeyScope::NewslettersSend => "newsletters:send",
+        }
+    }
+}
+
+impl FromStr for ApiKeyScope {
+    type Err = String;
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope {
+            "subscribers:read" => Ok(ApiKeyScope::SubscribersRead),
+            "newsletters:read" => Ok(ApiKeyScope::NewslettersRead),
+            "newsletters:send" => Ok(ApiKeyScope::NewslettersSend),
+            other => Err(format!("Unknown API key scope '{other}'.")),
+        }
+    }
+}
+
+impl fmt::Display for ApiKeyScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Machine client authenticated by an `Authorization: Bearer` API key.
+/// Usable as an extractor by any handler of the admin server, reusing the key validated by
+/// the authentication middleware when it already ran for the request.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedApiKey {
+    pub key_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<ApiKeyScope>,
+}
+
+impl AuthenticatedApiKey {
+    pub fn has_scope(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// Newly created key, the only time the key itself is available.
+pub struct GeneratedApiKey {
+    pub key_id: Uuid,
+    pub key: SecretString,
+    pub key_prefix: String,
+}
+
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+fn generate_api_key() -> GeneratedApiKey {
+    let mut rng = thread_rng();
+    let secret: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(API_KEY_SECRET_LENGTH)
+        .collect();
+    let key = format!("{}{}", API_KEY_PREFIX, secret);
+    GeneratedApiKey {
+        key_id: Uuid::new_v7(Timestamp::now(NoContext)),
+        key_prefix: key[..API_KEY_PREFIX.len() + API_KEY_DISPLAY_PREFIX_LENGTH].to_owned(),
+        key: SecretString::from(key),
+    }
+}
+
+/// Token of an `Authorization: Bearer <token>` header. `Ok(None)` when there is no such header.
+pub fn parse_bearer_token(headers: &HeaderMap) -> Result<Option<SecretString>, String> {
+    let header_value = match headers.get(AUTHORIZATION) {
+        Some(header_value) => header_value,
+        None => return Ok(None),
+    };
+    let header_value = header_value
+        .to_str()
+        .map_err(|_| "Authorization header is not valid UTF-8.".to_owned())?;
+    match header_value.split_once(' ') {
+        Some((scheme, token))
+            if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() =>
+        {
+            Ok(Some(SecretString::from(token.trim().to_owned())))
+        }
+        _ => Err("Authorization header must use the Bearer scheme.".to_owned()),
+    }
+}
+
+/// Look up an active key by its digest, recording its use.
+#[tracing::instrument(name = "Validating API key.", skip(postgres_client, key))]
+pub async fn validate_api_key(
+    postgres_client: &Object,
+    key: &SecretString,
+) -> Result<Option<AuthenticatedApiKey>, tokio_postgres::Error> {
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                UPDATE newsletter.api_keys SET last_used_at = now()
+                WHERE key_hash = $1 AND revoked_at IS NULL
+                RETURNING key_id, name, scopes
+            "#,
+        )
+        .await?;
+    let row = postgres_client
+        .query_opt(&statement, &[&hash_api_key(key.expose_secret())])
+        .await?;
+    Ok(row.map(|row| {
+        let scopes: Vec<String> = row.get("scopes");
+        AuthenticatedApiKey {
+            key_id: row.get("key_id"),
+            name: row.get("name"),
+            scopes: scopes
+                .iter()
+                .filter_map(|scope| scope.parse().ok())
+                .collect(),
+        }
+    }))
+}
+
+#[tracing::instrument(name = "Creating API key.", skip(postgres_client))]
+pub async fn create_api_key(
+    postgres_client: &Object,
+    name: &str,
+    scopes: &[ApiKeyScope],
+    created_by: Uuid,
+) -> Result<GeneratedApiKey, tokio_postgres::Error> {
+    let generated = generate_api_key();
+    let scopes: Vec<&str> = scopes.iter().map(ApiKeyScope::as_str).collect();
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                INSERT INTO newsletter.api_keys
+                    (key_id, name, key_prefix, key_hash, scopes, created_by)
+                VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .await?;
+    postgres_client
+        .execute(
+            &statement,
+            &[
+                &generated.key_id,
+                &name,
+                &generated.key_prefix,
+                &hash_api_key(generated.key.expose_secret()),
+                &scopes,
+                &created_by,
+            ],
+        )
+        .await?;
+    Ok(generated)
+}
+
+#[tracing::instrument(name = "Revoking API key.", skip(postgres_client))]
+pub async fn revoke_api_key(
+    postgres_client: &Object,
+    key_id: Uuid,
+) -> Result<u64, tokio_postgres::Error> {
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                UPDATE newsletter.api_keys SET revoked_at = now()
+                WHERE key_id = $1 AND revoked_at IS NULL
+            "#,
+        )
+        .await?;
+    postgres_client.execute(&statement, &[&key_id]).await
+}
+
+/// Allow admin sessions, which hold every scope, and API keys granted `scope`.
+pub fn require_scope(request: &HttpRequest, scope: ApiKeyScope) -> Result<(), HttpResponse> {
+    let extensions = request.extensions();
+    if extensions.get::<AuthenticatedAdmin>().is_some() {
+        return Ok(());
+    }
+    match extensions.get::<AuthenticatedApiKey>() {
+        Some(api_key) if api_key.has_scope(scope) => Ok(()),
+        Some(_) => Err(HttpResponse::Forbidden().body(format!("API key lacks scope '{scope}'."))),
+        None => Err(HttpResponse::Unauthorized().body("Login required.")),
+    }
+}
+
+/// Only allow admin sessions, e.g. API keys can't manage other API keys.
+pub fn require_admin_session(request: &HttpRequest) -> Result<AuthenticatedAdmin, HttpResponse> {
+    let extensions = request.extensions();
+    if let Some(admin) = extensions.get::<AuthenticatedAdmin>() {
+        return Ok(admin.clone());
+    }
+    if extensions.get::<AuthenticatedApiKey>().is_some() {
+        return Err(HttpResponse::Forbidden().body("Route requires an admin session."));
+    }
+    Err(HttpResponse::Unauthorized().body("Login required."))
+}
+
+impl FromRequest for AuthenticatedApiKey {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        if let Some(api_key) = request.extensions().get::<AuthenticatedApiKey>() {
+            let api_key = api_key.clone();
+            return Box::pin(async move { Ok(api_key) });
+        }
+        let request = request.clone();
+        Box::pin(async move {
+            let token = parse_bearer_token(request.headers())
+                .map_err(actix_web::error::ErrorUnauthorized)?
+                .ok_or_else(|| actix_web::error::ErrorUnauthorized("Missing bearer API key."))?;
+            let postgres_pool = request.app_data::<Arc<Pool>>().ok_or_else(|| {
+                tracing::error!("Could not retrieve postgres pool from app_data.");
+                actix_web::error::ErrorInternalServerError("DB pool error.")
+            })?;
+            let postgres_client = postgres_pool.get().await.map_err(|error| {
+                tracing::error!("Could not retrieve postgres client from pool, {}.", error);
+                actix_web::error::ErrorInternalServerError("DB client error.")
+            })?;
+            let api_key = validate_api_key(&postgres_client, &token)
+                .await
+                .map_err(|error| {
+                    tracing::error!("Failed to validate API key: {}", error);
+                    actix_web::error::ErrorInternalServerError("DB error.")
+                })?
+                .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid API key."))?;
+            request.extensions_mut().insert(api_key.clone());
+            Ok(api_key)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::authentication::{parse_bearer_token, ApiKeyScope};
+    use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use secrecy::ExposeSecret;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        headers
+    }
+
+    #[test]
+    fn bearer_token_is_parsed() {
+        let token = parse_bearer_token(&headers("Bearer nl_key"))
+            .unwrap()
+            .unwrap();
+        assert_eq!("nl_key", token.expose_secret());
+    }
+
+    #[test]
+    fn missing_authorization_header_is_not_an_error() {
+        assert!(parse_bearer_token(&HeaderMap::new()).unwrap().is_none());
+    }
+
+    #[test]
+    fn other_authorization_schemes_are_rejected() {
+        for authorization in ["Basic YWRtaW46cGFzcw==", "Bearer ", "nl_key"] {
+            assert!(
+                parse_bearer_token(&headers(authorization)).is_err(),
+                "Authorization '{}' should be rejected",
+                authorization
+            );
+        }
+    }
+
+    #[test]
+    fn scopes_round_trip_through_strings() {
+        for scope in [
+            ApiKeyScope::SubscribersRead,
+            ApiKeyScope::NewslettersRead,
+            ApiKeyScope::NewslettersSend,
+        ] {
+            assert_eq!(Ok(scope), scope.as_str().parse());
+        }
+        assert!("subscribers:write".parse::<ApiKeyScope>().is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::authentication::{
+    get_session_admin, verify_session_cookie, AuthenticatedApiKey, SESSION_COOKIE_NAME,
+};
+use crate::startup::HmacSecret;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpResponse};
+use deadpool_postgres::Pool;
+use std::sync::Arc;
+
+fn reject<B>(
+    request: ServiceRequest,
+    response: HttpResponse,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    Ok(request.into_response(response).map_into_right_body())
+}
+
+/// Guard for admin routes, only requests carrying a valid session cookie or bearer API key
+/// reach the handler. The caller is stored into the request extensions as either
+/// `AuthenticatedAdmin` or `AuthenticatedApiKey`, handlers check the scopes they require.
+pub async fn reject_anonymous_users<B: MessageBody>(
+    mut request: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    // Machine clients authenticate with API keys instead of sessions
+    if request.headers().contains_key(AUTHORIZATION) {
+        if let Err(error) = request.extract::<AuthenticatedApiKey>().await {
+            let response = error.as_response_error().error_response();
+            return reject(request, response);
+        }
+        return next
+            .call(request)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    }
+    let cookie_value = match request.cookie(SESSION_COOKIE_NAME) {
+        Some(cookie) => cookie.value().to_owned(),
+        None => {
+            return reject(
+                request,
+                HttpResponse::Unauthorized().body("Login required."),
+            )
+        }
+    };
+    let hmac_secret = match request.app_data::<Arc<HmacSecret>>() {
+        Some(hmac_secret) => hmac_secret.clone(),
+        None => {
+            tracing::error!("Could not retrieve HMAC secret from app_data.");
+            return reject(request, HttpResponse::InternalServerError().finish());
+        }
+    };
+    let session_id = match verify_session_cookie(&cookie_value, &hmac_secret.0) {
+        Some(session_id) => session_id,
+        None => {
+            tracing::warn!("Rejected admin session cookie with invalid signature");
+            return reject(
+                request,
+                HttpResponse::Unauthorized().body("Login required."),
+            );
+        }
+    };
+    let postgres_pool = match request.app_data::<Arc<Pool>>() {
+        Some(postgres_pool) => postgres_pool.clone(),
+        None => {
+            tracing::error!("Could not retrieve postgres pool from app_data.");
+            return reject(request, HttpResponse::InternalServerError().finish());
+        }
+    };
+    let postgres_client = match postgres_pool.get().await {
+        Ok(postgres_client) => postgres_client,
+        Err(error) => {
+            tracing::error!("Could not retrieve postgres client from pool, {}.", error);
+            return reject(request, HttpResponse::InternalServerError().finish());
+        }
+    };
+    let admin = match get_session_admin(&postgres_client, &session_id).await {
+        Ok(Some(admin)) => admin,
+        Ok(None) => {
+            return reject(
+                request,
+                HttpResponse::Unauthorized().body("Session expired, login required."),
+            )
+        }
+        Err(error) => {
+            tracing::error!("Failed to retrieve admin session: {}", error);
+            return reject(request, HttpResponse::InternalServerError().finish());
+        }
+    };
+    // Return the client to the pool before running the handler
+    std::mem::drop(postgres_client);
+    request.extensions_mut().insert(admin);
+    next.call(request)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
@@ -0,0 +1,11 @@
+mod api_key;
+mod middleware;
+mod password;
+mod session;
+
+pub use api_key::*;
+pub use middleware::reject_anonymous_users;
+pub use password::{
+    bootstrap_admin_user, compute_password_hash, validate_credentials, verify_password_hash,
+};
+pub use session::*;
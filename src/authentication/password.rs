@@ -0,0 +1,173 @@
+use crate::configuration::AdminBootstrapSettings;
+use anyhow::{anyhow, Context, Error, Result};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
+use deadpool_postgres::{Object, Pool};
+use secrecy::{ExposeSecret, SecretString};
+use std::sync::OnceLock;
+use uuid::{NoContext, Timestamp, Uuid};
+
+// Hash verified when the username is unknown, so that response times don't reveal valid usernames
+static FALLBACK_PASSWORD_HASH: OnceLock<SecretString> = OnceLock::new();
+
+fn argon2() -> Result<Argon2<'static>, Error> {
+    // OWASP recommended minimum: 19 MiB of memory, 2 iterations, 1 degree of parallelism
+    let params = Params::new(19456, 2, 1, None).map_err(|error| anyhow!(error))?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// Hash a password into an Argon2id PHC string with a random salt.
+pub fn compute_password_hash(password: &SecretString) -> Result<SecretString, Error> {
+    let salt =
+        SaltString::encode_b64(&rand::random::<[u8; 16]>()).map_err(|error| anyhow!(error))?;
+    let password_hash = argon2()?
+        .hash_password(password.expose_secret().as_bytes(), &salt)
+        .map_err(|error| anyhow!(error))
+        .with_context(|| {
+            format!(
+                "{}::authentication::compute_password_hash: Failed to hash password",
+                env!("CARGO_PKG_NAME")
+            )
+        })?;
+    Ok(SecretString::from(password_hash.to_string()))
+}
+
+/// Check a candidate password against a PHC string, the parameters are read from the hash itself.
+pub fn verify_password_hash(
+    expected_password_hash: &SecretString,
+    password_candidate: &SecretString,
+) -> Result<bool, Error> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .map_err(|error| anyhow!(error))
+        .with_context(|| {
+            format!(
+                "{}::authentication::verify_password_hash: Failed to parse stored password hash",
+                env!("CARGO_PKG_NAME")
+            )
+        })?;
+    Ok(argon2()?
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .is_ok())
+}
+
+fn fallback_password_hash() -> Result<SecretString, Error> {
+    if let Some(password_hash) = FALLBACK_PASSWORD_HASH.get() {
+        return Ok(password_hash.clone());
+    }
+    let password_hash = compute_password_hash(&SecretString::from(
+        "fallback-password-never-matching".to_owned(),
+    ))?;
+    Ok(FALLBACK_PASSWORD_HASH.get_or_init(|| password_hash).clone())
+}
+
+/// Return the id of the user matching both username and password.
+#[tracing::instrument(
+    name = "Validating admin credentials.",
+    skip(postgres_client, password)
+)]
+pub async fn validate_credentials(
+    postgres_client: &Object,
+    username: &str,
+    password: SecretString,
+) -> Result<Option<Uuid>, Error> {
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                SELECT user_id, password_hash FROM newsletter.users WHERE username = $1
+            "#,
+        )
+        .await?;
+    let row = postgres_client.query_opt(&statement, &[&username]).await?;
+    let (user_id, expected_password_hash) = match row {
+        Some(row) => (
+            Some(row.get::<_, Uuid>("user_id")),
+            SecretString::from(row.get::<_, String>("password_hash")),
+        ),
+        None => (None, fallback_password_hash()?),
+    };
+    // Hashing is CPU-bound, keep it away from the async executor threads
+    let is_valid = tokio::task::spawn_blocking(move || {
+        verify_password_hash(&expected_password_hash, &password)
+    })
+    .await??;
+    Ok(user_id.filter(|_| is_valid))
+}
+
+/// Create the administrator from settings unless some user already exists.
+#[tracing::instrument(name = "Bootstrapping admin user.", skip(postgres_pool, settings), fields(username = %settings.username))]
+pub async fn bootstrap_admin_user(
+    postgres_pool: &Pool,
+    settings: &AdminBootstrapSettings,
+) -> Result<bool, Error> {
+    let postgres_client = postgres_pool.get().await.with_context(|| {
+        format!(
+            "{}::authentication::bootstrap_admin_user: Failed to retrieve client from pool",
+            env!("CARGO_PKG_NAME")
+        )
+    })?;
+    let password = settings.password.clone();
+    let password_hash =
+        tokio::task::spawn_blocking(move || compute_password_hash(&password)).await??;
+    let user_id: Uuid = Uuid::new_v7(Timestamp::now(NoContext));
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                INSERT INTO newsletter.users (user_id, username, password_hash)
+                SELECT $1, $2, $3
+                WHERE NOT EXISTS (SELECT 1 FROM newsletter.users)
+                ON CONFLICT DO NOTHING
+            "#,
+        )
+        .await?;
+    let inserted = postgres_client
+        .execute(
+            &statement,
+            &[&user_id, &settings.username, &password_hash.expose_secret()],
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "{}::authentication::bootstrap_admin_user: Failed to insert admin user '{}'",
+                env!("CARGO_PKG_NAME"),
+                settings.username
+            )
+        })?;
+    if inserted > 0 {
+        tracing::info!("Created bootstrap admin user '{}'", settings.username);
+    }
+    Ok(inserted > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::authentication::{compute_password_hash, verify_password_hash};
+    use claims::assert_ok;
+    use secrecy::{ExposeSecret, SecretString};
+
+    fn secret(value: &str) -> SecretString {
+        SecretString::from(value.to_owned())
+    }
+
+    #[test]
+    fn password_hash_is_argon2id_phc_string() {
+        let password_hash = assert_ok!(compute_password_hash(&secret("correct horse")));
+        assert!(password_hash.expose_secret().starts_with("$argon2id$v=19$"));
+    }
+
+    #[test]
+    fn password_hash_verifies_only_matching_password() {
+        let password_hash = compute_password_hash(&secret("correct horse")).unwrap();
+        assert!(verify_password_hash(&password_hash, &secret("correct horse")).unwrap());
+        assert!(!verify_password_hash(&password_hash, &secret("battery staple")).unwrap());
+    }
+
+    #[test]
+    fn password_hashes_are_salted() {
+        let first = compute_password_hash(&secret("correct horse")).unwrap();
+        let second = compute_password_hash(&secret("correct horse")).unwrap();
+        assert_ne!(first.expose_secret(), second.expose_secret());
+    }
+}
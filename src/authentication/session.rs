@@ -0,0 +1,154 @@
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+use std::time::Duration;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub static SESSION_COOKIE_NAME: &str = "admin_session";
+pub static DEFAULT_SESSION_TTL_SECONDS: u64 = 43200;
+pub static SESSION_ID_LENGTH: usize = 32;
+
+// Domain separation from other values signed with the same key, e.g. unsubscribe tokens
+static SESSION_COOKIE_PURPOSE: &[u8] = b"newsletter-admin-session:";
+
+/// Administrator owning the session of the current request, set by the authentication middleware.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedAdmin {
+    pub user_id: Uuid,
+    pub username: String,
+    pub session_id: String,
+}
+
+fn new_mac(hmac_secret: &SecretString) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC can take key of any size");
+    mac.update(SESSION_COOKIE_PURPOSE);
+    mac
+}
+
+/// Cookie value `<session id>.<hex HMAC-SHA256 of the session id>`.
+pub fn sign_session_cookie(session_id: &str, hmac_secret: &SecretString) -> String {
+    let mut mac = new_mac(hmac_secret);
+    mac.update(session_id.as_bytes());
+    format!(
+        "{}.{}",
+        session_id,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+/// Return the session id of a cookie value if its signature is valid.
+pub fn verify_session_cookie(cookie_value: &str, hmac_secret: &SecretString) -> Option<String> {
+    let (session_id, signature) = cookie_value.split_once('.')?;
+    let signature = hex::decode(signature).ok()?;
+    let mut mac = new_mac(hmac_secret);
+    mac.update(session_id.as_bytes());
+    mac.verify_slice(&signature).ok()?;
+    Some(session_id.to_owned())
+}
+
+fn generate_session_id() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(SESSION_ID_LENGTH)
+        .collect()
+}
+
+#[tracing::instrument(name = "Creating admin session.", skip(postgres_client))]
+pub async fn create_session(
+    postgres_client: &deadpool_postgres::Object,
+    user_id: Uuid,
+    session_ttl: Duration,
+) -> Result<String, tokio_postgres::Error> {
+    // Expired sessions are cleaned up on every login
+    let cleanup_statement = postgres_client
+        .prepare_cached("DELETE FROM newsletter.admin_sessions WHERE expires_at <= now()")
+        .await?;
+    postgres_client.execute(&cleanup_statement, &[]).await?;
+    let session_id = generate_session_id();
+    let session_ttl_seconds = session_ttl.as_secs_f64();
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                INSERT INTO newsletter.admin_sessions (session_id, user_id, expires_at)
+                VALUES ($1, $2, now() + make_interval(secs => $3::FLOAT8))
+            "#,
+        )
+        .await?;
+    postgres_client
+        .execute(&statement, &[&session_id, &user_id, &session_ttl_seconds])
+        .await?;
+    Ok(session_id)
+}
+
+#[tracing::instrument(name = "Retrieving admin session.", skip(postgres_client, session_id))]
+pub async fn get_session_admin(
+    postgres_client: &deadpool_postgres::Object,
+    session_id: &str,
+) -> Result<Option<AuthenticatedAdmin>, tokio_postgres::Error> {
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                SELECT users.user_id, users.username
+                FROM newsletter.admin_sessions AS sessions
+                JOIN newsletter.users AS users ON users.user_id = sessions.user_id
+                WHERE sessions.session_id = $1 AND sessions.expires_at > now()
+            "#,
+        )
+        .await?;
+    let row = postgres_client
+        .query_opt(&statement, &[&session_id])
+        .await?;
+    Ok(row.map(|row| AuthenticatedAdmin {
+        user_id: row.get("user_id"),
+        username: row.get("username"),
+        session_id: session_id.to_owned(),
+    }))
+}
+
+#[tracing::instrument(name = "Deleting admin session.", skip(postgres_client, session_id))]
+pub async fn delete_session(
+    postgres_client: &deadpool_postgres::Object,
+    session_id: &str,
+) -> Result<u64, tokio_postgres::Error> {
+    let statement = postgres_client
+        .prepare_cached("DELETE FROM newsletter.admin_sessions WHERE session_id = $1")
+        .await?;
+    postgres_client.execute(&statement, &[&session_id]).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::authentication::{sign_session_cookie, verify_session_cookie};
+    use secrecy::SecretString;
+
+    fn secret(value: &str) -> SecretString {
+        SecretString::from(value.to_owned())
+    }
+
+    #[test]
+    fn signed_session_cookie_is_verified() {
+        let cookie_value = sign_session_cookie("session", &secret("hmac-secret"));
+        assert_eq!(
+            Some("session".to_owned()),
+            verify_session_cookie(&cookie_value, &secret("hmac-secret"))
+        );
+    }
+
+    #[test]
+    fn session_cookie_with_forged_signature_is_rejected() {
+        let cookie_value = sign_session_cookie("session", &secret("other-secret"));
+        assert_eq!(
+            None,
+            verify_session_cookie(&cookie_value, &secret("hmac-secret"))
+        );
+        assert_eq!(
+            None,
+            verify_session_cookie("session", &secret("hmac-secret"))
+        );
+    }
+}
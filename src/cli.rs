@@ -0,0 +1,236 @@
+use crate::configuration::DatabaseSettings;
+use crate::postgres::{
+    create_database, generate_connection_pool, get_migration_status, repair_migration_checksums,
+    rollback_migrations, run_pending_migrations,
+};
+use anyhow::{Context, Error, Result};
+use deadpool_postgres::Object;
+use std::fmt::Write;
+
+pub static USAGE: &str = "Usage:
+  newsletter-rs                         Run the HTTP servers
+  newsletter-rs --help                  Print this message
+  newsletter-rs migrate status          List applied and pending migrations
+  newsletter-rs migrate up [--dry-run]  Apply pending migrations, or only list them
+  newsletter-rs migrate verify          Fail unless every migration is applied unchanged
+  newsletter-rs migrate repair          Record the checksum of reviewed, edited migrations
+  newsletter-rs migrate rollback --to <version>
+                                        Revert migrations newer than version with their down scripts";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Help,
+    Migrate(MigrateCommand),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MigrateCommand {
+    Status,
+    Up { dry_run: bool },
+    Verify,
+    Repair,
+    Rollback { to: i64 },
+}
+
+/// Parse the command line arguments following the program name.
+pub fn parse_args<I>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let args: Vec<String> = args.into_iter().collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] => Ok(Command::Serve),
+        ["--help"] | ["-h"] | ["help"] => Ok(Command::Help),
+        ["migrate", "status"] => Ok(Command::Migrate(MigrateCommand::Status)),
+        ["migrate", "up"] => Ok(Command::Migrate(MigrateCommand::Up { dry_run: false })),
+        ["migrate", "up", "--dry-run"] => {
+            Ok(Command::Migrate(MigrateCommand::Up { dry_run: true }))
+        }
+        ["migrate", "verify"] => Ok(Command::Migrate(MigrateCommand::Verify)),
+        ["migrate", "repair"] => Ok(Command::Migrate(MigrateCommand::Repair)),
+        ["migrate", "rollback", "--to", version] => match version.parse() {
+            Ok(to) => Ok(Command::Migrate(MigrateCommand::Rollback { to })),
+            Err(_) => Err(format!(
+                "Invalid version '{}', expected the numeric prefix of a migration script.\n{}",
+                version, USAGE
+            )),
+        },
+        _ => Err(format!(
+            "Unknown arguments '{}'.\n{}",
+            args.join(" "),
+            USAGE
+        )),
+    }
+}
+
+/// Human readable outcome of a migrate subcommand, `success` sets the exit code.
+#[derive(Debug)]
+pub struct MigrateReport {
+    pub report: String,
+    pub success: bool,
+}
+
+#[tracing::instrument(name = "Running migrate command.")]
+pub async fn run_migrate_command(
+    command: &MigrateCommand,
+    mut database_settings: DatabaseSettings,
+) -> Result<MigrateReport, Error> {
+    let mut report = String::new();
+    match command {
+        MigrateCommand::Status => {
+            let postgres_client = connect(&database_settings).await?;
+            let status = get_migration_status(&postgres_client, &database_settings).await?;
+            write!(report, "{}", status)?;
+            Ok(MigrateReport {
+                report,
+                success: true,
+            })
+        }
+        MigrateCommand::Verify => {
+            let postgres_client = connect(&database_settings).await?;
+            let status = get_migration_status(&postgres_client, &database_settings).await?;
+            let success = status.is_verified();
+            write!(report, "{}", status)?;
+            if success {
+                writeln!(report, "Verified: every migration is applied unchanged.")?;
+            } else {
+                writeln!(report, "Verification failed.")?;
+            }
+            Ok(MigrateReport { report, success })
+        }
+        MigrateCommand::Repair => {
+            let mut postgres_client = connect(&database_settings).await?;
+            let repaired =
+                repair_migration_checksums(&mut postgres_client, &database_settings).await?;
+            writeln!(report, "Repaired {} migration checksums:", repaired.len())?;
+            for drifted in &repaired {
+                writeln!(
+                    report,
+                    "  {} (md5 {} -> {})",
+                    drifted.applied.filename,
+                    drifted.applied.md5_hash.simple(),
+                    drifted.script.md5_hash.simple()
+                )?;
+            }
+            Ok(MigrateReport {
+                report,
+                success: true,
+            })
+        }
+        MigrateCommand::Rollback { to } => {
+            let mut postgres_client = connect(&database_settings).await?;
+            let reverted =
+                rollback_migrations(&mut postgres_client, &database_settings, *to).await?;
+            writeln!(report, "Rolled back {} migrations:", reverted.len())?;
+            for script in &reverted {
+                writeln!(report, "  {}", script.filename)?;
+            }
+            Ok(MigrateReport {
+                report,
+                success: true,
+            })
+        }
+        MigrateCommand::Up { dry_run: true } => {
+            let postgres_client = connect(&database_settings).await?;
+            let status = get_migration_status(&postgres_client, &database_settings).await?;
+            writeln!(
+                report,
+                "Dry run, would apply {} migrations:",
+                status.pending.len()
+            )?;
+            for script in &status.pending {
+                writeln!(report, "  {}", script.filename)?;
+            }
+            Ok(MigrateReport {
+                report,
+                success: true,
+            })
+        }
+        MigrateCommand::Up { dry_run: false } => {
+            let postgres_pool = create_database(&mut database_settings).await?;
+            let mut postgres_client = postgres_pool.get().await.with_context(|| {
+                format!(
+                    "{}::cli::run_migrate_command: Failed to connect to database",
+                    env!("CARGO_PKG_NAME")
+                )
+            })?;
+            let status = run_pending_migrations(&mut postgres_client, &database_settings).await?;
+            writeln!(report, "Applied {} migrations:", status.pending.len())?;
+            for script in &status.pending {
+                writeln!(report, "  {}", script.filename)?;
+            }
+            Ok(MigrateReport {
+                report,
+                success: true,
+            })
+        }
+    }
+}
+
+async fn connect(database_settings: &DatabaseSettings) -> Result<Object, Error> {
+    let postgres_pool = generate_connection_pool(
+        database_settings.connection().build(),
+        &database_settings.ssl,
+        database_settings.pool.as_ref(),
+    )?;
+    postgres_pool.get().await.with_context(|| {
+        format!(
+            "{}::cli::connect: Failed to connect to database '{}'",
+            env!("CARGO_PKG_NAME"),
+            database_settings.connection()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{parse_args, Command, MigrateCommand};
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn no_arguments_serve() {
+        assert_eq!(Ok(Command::Serve), parse_args(args("")));
+        assert_eq!(Ok(Command::Help), parse_args(args("--help")));
+    }
+
+    #[test]
+    fn migrate_subcommands_are_parsed() {
+        let test_cases = vec![
+            ("migrate status", MigrateCommand::Status),
+            ("migrate up", MigrateCommand::Up { dry_run: false }),
+            ("migrate up --dry-run", MigrateCommand::Up { dry_run: true }),
+            ("migrate verify", MigrateCommand::Verify),
+            ("migrate repair", MigrateCommand::Repair),
+            (
+                "migrate rollback --to 20241019100000",
+                MigrateCommand::Rollback { to: 20241019100000 },
+            ),
+        ];
+        for (line, expected) in test_cases {
+            assert_eq!(Ok(Command::Migrate(expected)), parse_args(args(line)));
+        }
+    }
+
+    #[test]
+    fn unknown_arguments_are_rejected() {
+        for line in [
+            "migrate",
+            "migrate down",
+            "migrate up --force",
+            "migrate rollback",
+            "migrate rollback --to latest",
+            "serve",
+        ] {
+            assert!(
+                parse_args(args(line)).is_err(),
+                "'{}' should be rejected",
+                line
+            );
+        }
+    }
+}
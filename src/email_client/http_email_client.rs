@@ -0,0 +1,210 @@
+use crate::email_client::{EmailClient, EmailMessage};
+use crate::subscription::SubscriptionFilteredEmail;
+use anyhow::{Context, Error, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use secrecy::{ExposeSecret, SecretString};
+use std::time::Duration;
+
+pub static SERVER_TOKEN_HEADER: &str = "X-Postmark-Server-Token";
+
+/// Backend delivering emails through a JSON over HTTP API, following Postmark's format.
+pub struct HttpEmailClient {
+    http_client: Client,
+    base_url: String,
+    sender: SubscriptionFilteredEmail,
+    authorization_token: SecretString,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    headers: Vec<MessageHeader<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MessageHeader<'a> {
+    name: &'a str,
+    value: String,
+}
+
+impl HttpEmailClient {
+    pub fn new(
+        base_url: String,
+        sender: SubscriptionFilteredEmail,
+        authorization_token: SecretString,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        let http_client = Client::builder()
+            .timeout(timeout)
+            .build()
+            .with_context(|| {
+                format!(
+                    "{}::email_client::HttpEmailClient::new: Failed to build HTTP client",
+                    env!("CARGO_PKG_NAME")
+                )
+            })?;
+        Ok(Self {
+            http_client,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            sender,
+            authorization_token,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailClient for HttpEmailClient {
+    #[tracing::instrument(name = "Sending email through HTTP API.", skip(self, message), fields(recipient = %message.to))]
+    async fn send_email(&self, message: &EmailMessage) -> Result<(), Error> {
+        let url = format!("{}/email", self.base_url);
+        let request_body = SendEmailRequest {
+            from: self.sender.as_ref(),
+            to: message.to.as_ref(),
+            subject: &message.subject,
+            html_body: &message.html_body,
+            text_body: &message.text_body,
+            headers: message
+                .list_unsubscribe_headers()
+                .into_iter()
+                .map(|(name, value)| MessageHeader { name, value })
+                .collect(),
+        };
+        self.http_client
+            .post(&url)
+            .header(
+                SERVER_TOKEN_HEADER,
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await
+            .with_context(|| {
+                format!(
+                    "{}::email_client::HttpEmailClient::send_email: Failed to send request to '{}'",
+                    env!("CARGO_PKG_NAME"),
+                    url
+                )
+            })?
+            .error_for_status()
+            .with_context(|| {
+                format!(
+                    "{}::email_client::HttpEmailClient::send_email: Email API '{}' rejected the request",
+                    env!("CARGO_PKG_NAME"),
+                    url
+                )
+            })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::email_client::{EmailClient, EmailMessage, HttpEmailClient};
+    use crate::subscription::SubscriptionFilteredEmail;
+    use claims::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::SecretString;
+    use std::time::Duration;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                ["From", "To", "Subject", "HtmlBody", "TextBody", "Headers"]
+                    .iter()
+                    .all(|field| body.get(field).is_some())
+            } else {
+                false
+            }
+        }
+    }
+
+    fn email() -> SubscriptionFilteredEmail {
+        SubscriptionFilteredEmail::parse(&SafeEmail().fake::<String>()).unwrap()
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            to: email(),
+            subject: Sentence(1..2).fake(),
+            html_body: Paragraph(1..10).fake(),
+            text_body: Paragraph(1..10).fake(),
+            unsubscribe_link: "https://drconopoima.com/subscription/unsubscribe?token=t".to_owned(),
+        }
+    }
+
+    fn email_client(base_url: String) -> HttpEmailClient {
+        HttpEmailClient::new(
+            base_url,
+            email(),
+            SecretString::from(Faker.fake::<String>()),
+            Duration::from_millis(200),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let _ = email_client.send_email(&message()).await;
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_server_returns_200() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        assert_ok!(email_client.send_email(&message()).await);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        assert_err!(email_client.send_email(&message()).await);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(180)))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        assert_err!(email_client.send_email(&message()).await);
+    }
+}
@@ -0,0 +1,22 @@
+use crate::email_client::{EmailClient, EmailMessage};
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+
+/// Backend that only records outgoing emails into the tracing output, useful for local development.
+#[derive(Default)]
+pub struct LogEmailClient;
+
+#[async_trait]
+impl EmailClient for LogEmailClient {
+    #[tracing::instrument(name = "Logging outgoing email.", skip(self, message), fields(recipient = %message.to))]
+    async fn send_email(&self, message: &EmailMessage) -> Result<(), Error> {
+        tracing::info!(
+            "Email to '{}' with subject '{}' and unsubscribe link '{}' was not delivered (log email backend): {}",
+            message.to,
+            message.subject,
+            message.unsubscribe_link,
+            message.text_body
+        );
+        Ok(())
+    }
+}
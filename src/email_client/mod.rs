@@ -0,0 +1,101 @@
+mod http_email_client;
+mod log_email_client;
+mod smtp_email_client;
+
+pub use http_email_client::HttpEmailClient;
+pub use log_email_client::LogEmailClient;
+pub use smtp_email_client::SmtpEmailClient;
+
+use crate::configuration::{EmailBackend, EmailSettings};
+use crate::subscription::SubscriptionFilteredEmail;
+use anyhow::{anyhow, Context, Error, Result};
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub static DEFAULT_EMAIL_TIMEOUT_MS: u32 = 10000;
+pub static LIST_UNSUBSCRIBE_HEADER: &str = "List-Unsubscribe";
+pub static LIST_UNSUBSCRIBE_POST_HEADER: &str = "List-Unsubscribe-Post";
+
+#[derive(Clone, Debug)]
+pub struct EmailMessage {
+    pub to: SubscriptionFilteredEmail,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    // Signed one-click link, required in every outgoing email by bulk-sender rules
+    pub unsubscribe_link: String,
+}
+
+impl EmailMessage {
+    /// RFC 8058 one-click unsubscribe headers, advertising a POST to the unsubscribe link.
+    pub fn list_unsubscribe_headers(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                LIST_UNSUBSCRIBE_HEADER,
+                format!("<{}>", self.unsubscribe_link),
+            ),
+            (
+                LIST_UNSUBSCRIBE_POST_HEADER,
+                "List-Unsubscribe=One-Click".to_owned(),
+            ),
+        ]
+    }
+}
+
+/// Delivery backend for outgoing emails. The sender address is owned by the implementation.
+#[async_trait]
+pub trait EmailClient: Send + Sync {
+    async fn send_email(&self, message: &EmailMessage) -> Result<(), Error>;
+}
+
+/// Build the email backend selected in settings, defaulting to `LogEmailClient` when unset.
+pub fn build_email_client(settings: Option<&EmailSettings>) -> Result<Arc<dyn EmailClient>, Error> {
+    let settings = match settings {
+        Some(settings) => settings,
+        None => {
+            tracing::warn!("No email settings were provided, outgoing emails will only be logged");
+            return Ok(Arc::new(LogEmailClient));
+        }
+    };
+    let sender = SubscriptionFilteredEmail::parse(&settings.sender)
+        .map_err(|error| anyhow!(error))
+        .with_context(|| {
+            format!(
+                "{}::email_client::build_email_client: Invalid sender email address",
+                env!("CARGO_PKG_NAME")
+            )
+        })?;
+    let timeout = Duration::from_millis(
+        settings
+            .timeoutms
+            .unwrap_or(DEFAULT_EMAIL_TIMEOUT_MS)
+            .into(),
+    );
+    Ok(match settings.backend {
+        EmailBackend::Log => Arc::new(LogEmailClient),
+        EmailBackend::Smtp => {
+            let smtp_settings = settings.smtp.as_ref().with_context(|| {
+                format!(
+                    "{}::email_client::build_email_client: Missing 'email.smtp' settings for smtp backend",
+                    env!("CARGO_PKG_NAME")
+                )
+            })?;
+            Arc::new(SmtpEmailClient::new(sender, smtp_settings, timeout)?)
+        }
+        EmailBackend::Http => {
+            let http_settings = settings.http.as_ref().with_context(|| {
+                format!(
+                    "{}::email_client::build_email_client: Missing 'email.http' settings for http backend",
+                    env!("CARGO_PKG_NAME")
+                )
+            })?;
+            Arc::new(HttpEmailClient::new(
+                http_settings.baseurl.to_owned(),
+                sender,
+                http_settings.token.clone(),
+                timeout,
+            )?)
+        }
+    })
+}
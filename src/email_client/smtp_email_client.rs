@@ -0,0 +1,242 @@
+use crate::configuration::{SmtpSettings, SmtpTlsMode};
+use crate::email_client::{EmailClient, EmailMessage};
+use crate::subscription::SubscriptionFilteredEmail;
+use anyhow::{anyhow, Context, Error, Result};
+use async_trait::async_trait;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::ExposeSecret;
+use std::time::Duration;
+
+/// Backend delivering emails to an SMTP relay.
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+}
+
+impl SmtpEmailClient {
+    pub fn new(
+        sender: SubscriptionFilteredEmail,
+        smtp_settings: &SmtpSettings,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        let mut builder = match smtp_settings.tls {
+            SmtpTlsMode::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp_settings.host)
+            }
+            SmtpTlsMode::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp_settings.host)
+                    .with_context(|| {
+                        format!(
+                            "{}::email_client::SmtpEmailClient::new: Failed to configure STARTTLS relay '{}'",
+                            env!("CARGO_PKG_NAME"),
+                            smtp_settings.host
+                        )
+                    })?
+            }
+            SmtpTlsMode::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp_settings.host)
+                .with_context(|| {
+                    format!(
+                        "{}::email_client::SmtpEmailClient::new: Failed to configure TLS relay '{}'",
+                        env!("CARGO_PKG_NAME"),
+                        smtp_settings.host
+                    )
+                })?,
+        }
+        .port(smtp_settings.port)
+        .timeout(Some(timeout));
+        match (&smtp_settings.username, &smtp_settings.password) {
+            (Some(username), Some(password)) => {
+                builder = builder.credentials(Credentials::new(
+                    username.to_owned(),
+                    password.expose_secret().to_owned(),
+                ));
+            }
+            (None, None) => {}
+            _ => {
+                return Err(anyhow!(
+                    "{}::email_client::SmtpEmailClient::new: email.smtp.username and email.smtp.password must be set together",
+                    env!("CARGO_PKG_NAME")
+                ))
+            }
+        }
+        let sender = sender.parse::<Mailbox>().with_context(|| {
+            format!(
+                "{}::email_client::SmtpEmailClient::new: Invalid sender mailbox '{}'",
+                env!("CARGO_PKG_NAME"),
+                sender
+            )
+        })?;
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailClient for SmtpEmailClient {
+    #[tracing::instrument(name = "Sending email through SMTP.", skip(self, message), fields(recipient = %message.to))]
+    async fn send_email(&self, message: &EmailMessage) -> Result<(), Error> {
+        let recipient = message.to.parse::<Mailbox>().with_context(|| {
+            format!(
+                "{}::email_client::SmtpEmailClient::send_email: Invalid recipient mailbox '{}'",
+                env!("CARGO_PKG_NAME"),
+                message.to
+            )
+        })?;
+        let mut builder = Message::builder()
+            .from(self.sender.clone())
+            .to(recipient)
+            .subject(&message.subject);
+        for (name, value) in message.list_unsubscribe_headers() {
+            let name = HeaderName::new_from_ascii_str(name);
+            builder = builder.raw_header(HeaderValue::new(name, value));
+        }
+        let email = builder
+            .multipart(MultiPart::alternative_plain_html(
+                message.text_body.to_owned(),
+                message.html_body.to_owned(),
+            ))
+            .with_context(|| {
+                format!(
+                    "{}::email_client::SmtpEmailClient::send_email: Failed to build email message",
+                    env!("CARGO_PKG_NAME")
+                )
+            })?;
+        self.transport.send(email).await.with_context(|| {
+            format!(
+                "{}::email_client::SmtpEmailClient::send_email: SMTP relay rejected the email",
+                env!("CARGO_PKG_NAME")
+            )
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::{SmtpSettings, SmtpTlsMode};
+    use crate::email_client::{EmailClient, EmailMessage, SmtpEmailClient};
+    use crate::subscription::SubscriptionFilteredEmail;
+    use claims::{assert_err, assert_ok};
+    use secrecy::SecretString;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    // Minimal SMTP relay accepting a single session, recording the DATA section
+    async fn launch_stub_smtp_server(rcpt_reply: &'static str) -> (u16, Arc<Mutex<String>>) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(String::new()));
+        let received_data = received.clone();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost stub\r\n").await.unwrap();
+            let mut in_data = false;
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        writer.write_all(b"250 OK queued\r\n").await.unwrap();
+                    } else {
+                        let mut data = received_data.lock().unwrap();
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let command = line.to_uppercase();
+                let reply: &str = if command.starts_with("EHLO") || command.starts_with("HELO") {
+                    "250 localhost\r\n"
+                } else if command.starts_with("RCPT") {
+                    rcpt_reply
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    "354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    "250 OK\r\n"
+                };
+                writer.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+        (port, received)
+    }
+
+    fn email_client(port: u16) -> SmtpEmailClient {
+        let smtp_settings = SmtpSettings {
+            host: "127.0.0.1".to_owned(),
+            port,
+            username: None,
+            password: None,
+            tls: SmtpTlsMode::None,
+        };
+        SmtpEmailClient::new(
+            SubscriptionFilteredEmail::parse("newsletter@drconopoima.com").unwrap(),
+            &smtp_settings,
+            Duration::from_secs(5),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn credentials_must_be_configured_together() {
+        let smtp_settings = SmtpSettings {
+            host: "127.0.0.1".to_owned(),
+            port: 25,
+            username: Some("newsletter".to_owned()),
+            password: None,
+            tls: SmtpTlsMode::None,
+        };
+        let sender = SubscriptionFilteredEmail::parse("newsletter@drconopoima.com").unwrap();
+        assert!(
+            SmtpEmailClient::new(sender.clone(), &smtp_settings, Duration::from_secs(5)).is_err()
+        );
+        let smtp_settings = SmtpSettings {
+            username: None,
+            password: Some(SecretString::from("password")),
+            ..smtp_settings
+        };
+        assert!(SmtpEmailClient::new(sender, &smtp_settings, Duration::from_secs(5)).is_err());
+    }
+
+    fn message() -> EmailMessage {
+        EmailMessage {
+            to: SubscriptionFilteredEmail::parse("subscriber@drconopoima.com").unwrap(),
+            subject: "Stub subject".to_owned(),
+            html_body: "<p>Stub html body</p>".to_owned(),
+            text_body: "Stub text body".to_owned(),
+            unsubscribe_link: "https://drconopoima.com/subscription/unsubscribe?token=t".to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_delivers_message_to_relay() {
+        let (port, received) = launch_stub_smtp_server("250 OK\r\n").await;
+        assert_ok!(email_client(port).send_email(&message()).await);
+        let data = received.lock().unwrap();
+        assert!(data.contains("Subject: Stub subject"));
+        assert!(data.contains("To: subscriber@drconopoima.com"));
+        assert!(data.contains("From: newsletter@drconopoima.com"));
+        assert!(data.contains("Stub text body"));
+        assert!(data.contains(
+            "List-Unsubscribe: <https://drconopoima.com/subscription/unsubscribe?token=t>"
+        ));
+        assert!(data.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_relay_rejects_recipient() {
+        let (port, _) = launch_stub_smtp_server("550 No such user\r\n").await;
+        assert_err!(email_client(port).send_email(&message()).await);
+    }
+}
@@ -0,0 +1,345 @@
+use crate::configuration::DeliveryWorkerSettings;
+use crate::email_client::{EmailClient, EmailMessage};
+use crate::subscription::{SubscriptionFilteredEmail, UnsubscribeToken};
+use anyhow::{anyhow, Error, Result};
+use deadpool_postgres::{Pool, Transaction};
+use secrecy::SecretString;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+struct DeliveryTask {
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    email: String,
+    status: String,
+    n_retries: i32,
+}
+
+// Base URL and key to sign the unsubscribe link of every delivered issue
+pub struct UnsubscribeLinkSettings {
+    pub base_url: String,
+    pub hmac_secret: SecretString,
+}
+
+/// Poll the delivery queue until the process stops. Safe to run from several replicas at once:
+/// every task is locked by the transaction delivering it and skipped by other workers.
+pub async fn run_worker_until_stopped(
+    postgres_pool: Arc<Pool>,
+    email_client: Arc<dyn EmailClient>,
+    settings: DeliveryWorkerSettings,
+    unsubscribe_link_settings: UnsubscribeLinkSettings,
+) -> Result<(), Error> {
+    if !settings.enabled {
+        tracing::info!("Newsletter delivery worker is disabled by settings");
+        return futures::future::pending().await;
+    }
+    loop {
+        match try_execute_task(
+            &postgres_pool,
+            email_client.as_ref(),
+            &settings,
+            &unsubscribe_link_settings,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_millis(settings.pollintervalms)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Err(error) => {
+                tracing::error!("Failed to execute newsletter delivery task: {:?}", error);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+/// Delay before the next attempt after `n_retries` failed ones, doubling up to the maximum.
+pub fn retry_backoff(settings: &DeliveryWorkerSettings, n_retries: i32) -> Duration {
+    let exponent = n_retries.clamp(0, 32) as u32;
+    let backoff_ms = settings
+        .initialbackoffms
+        .saturating_mul(2u64.saturating_pow(exponent))
+        .min(settings.maxbackoffms);
+    Duration::from_millis(backoff_ms)
+}
+
+#[tracing::instrument(
+    name = "Executing newsletter delivery task.",
+    skip_all,
+    fields(issue_id = tracing::field::Empty, subscriber_id = tracing::field::Empty)
+)]
+pub async fn try_execute_task(
+    postgres_pool: &Pool,
+    email_client: &dyn EmailClient,
+    settings: &DeliveryWorkerSettings,
+    unsubscribe_link_settings: &UnsubscribeLinkSettings,
+) -> Result<ExecutionOutcome, Error> {
+    let mut postgres_client = postgres_pool.get().await?;
+    let transaction = postgres_client.transaction().await?;
+    let task = match dequeue_task(&transaction).await? {
+        Some(task) => task,
+        None => {
+            transaction.commit().await?;
+            return Ok(ExecutionOutcome::EmptyQueue);
+        }
+    };
+    tracing::Span::current()
+        .record("issue_id", tracing::field::display(&task.issue_id))
+        .record(
+            "subscriber_id",
+            tracing::field::display(&task.subscriber_id),
+        );
+    // Subscribers leaving the list after the issue was published are not delivered to
+    if task.status != "confirmed" {
+        tracing::info!("Skipping delivery of newsletter issue to unsubscribed subscriber");
+        delete_task(&transaction, &task).await?;
+        record_failure(
+            &transaction,
+            &task,
+            task.n_retries,
+            "Subscriber unsubscribed before delivery",
+        )
+        .await?;
+        record_issue_progress(&transaction, task.issue_id, 0, 1).await?;
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let outcome =
+        send_issue_to_subscriber(&transaction, email_client, &task, unsubscribe_link_settings)
+            .await;
+    match outcome {
+        Ok(_) => {
+            delete_task(&transaction, &task).await?;
+            record_issue_progress(&transaction, task.issue_id, 1, 0).await?;
+        }
+        Err(error) => {
+            let n_retries = task.n_retries + 1;
+            let last_error = format!("{:#}", error);
+            if n_retries >= settings.maxretries {
+                tracing::error!(
+                    "Giving up delivery of newsletter issue after {} attempts: {}",
+                    n_retries,
+                    last_error
+                );
+                delete_task(&transaction, &task).await?;
+                record_failure(&transaction, &task, n_retries, &last_error).await?;
+                record_issue_progress(&transaction, task.issue_id, 0, 1).await?;
+            } else {
+                let backoff = retry_backoff(settings, task.n_retries);
+                tracing::warn!(
+                    "Failed delivery of newsletter issue, retrying in {}ms: {}",
+                    backoff.as_millis(),
+                    last_error
+                );
+                reschedule_task(&transaction, &task, n_retries, backoff, &last_error).await?;
+            }
+        }
+    }
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn dequeue_task(
+    transaction: &Transaction<'_>,
+) -> Result<Option<DeliveryTask>, tokio_postgres::Error> {
+    let statement = transaction
+        .prepare_cached(
+            r#"
+                SELECT queue.issue_id, queue.subscriber_id, queue.n_retries,
+                    subscription.email::TEXT AS email, subscription.status
+                FROM newsletter.issue_delivery_queue AS queue
+                JOIN newsletter.subscription AS subscription
+                    ON subscription.id = queue.subscriber_id
+                WHERE queue.execute_after <= now()
+                ORDER BY queue.execute_after
+                FOR UPDATE OF queue SKIP LOCKED
+                LIMIT 1
+            "#,
+        )
+        .await?;
+    let row = transaction.query_opt(&statement, &[]).await?;
+    Ok(row.map(|row| DeliveryTask {
+        issue_id: row.get("issue_id"),
+        subscriber_id: row.get("subscriber_id"),
+        email: row.get("email"),
+        status: row.get("status"),
+        n_retries: row.get("n_retries"),
+    }))
+}
+
+async fn send_issue_to_subscriber(
+    transaction: &Transaction<'_>,
+    email_client: &dyn EmailClient,
+    task: &DeliveryTask,
+    unsubscribe_link_settings: &UnsubscribeLinkSettings,
+) -> Result<(), Error> {
+    let statement = transaction
+        .prepare_cached(
+            r#"
+                SELECT title, html_content, text_content
+                FROM newsletter.newsletter_issues WHERE id = $1
+            "#,
+        )
+        .await?;
+    let issue = transaction.query_one(&statement, &[&task.issue_id]).await?;
+    let to = SubscriptionFilteredEmail::parse(&task.email).map_err(|error| anyhow!(error))?;
+    let message = EmailMessage {
+        to,
+        subject: issue.get("title"),
+        html_body: issue.get("html_content"),
+        text_body: issue.get("text_content"),
+        unsubscribe_link: UnsubscribeToken::sign(
+            task.subscriber_id,
+            &unsubscribe_link_settings.hmac_secret,
+        )
+        .link(&unsubscribe_link_settings.base_url),
+    };
+    email_client.send_email(&message).await
+}
+
+async fn delete_task(
+    transaction: &Transaction<'_>,
+    task: &DeliveryTask,
+) -> Result<u64, tokio_postgres::Error> {
+    let statement = transaction
+        .prepare_cached(
+            r#"
+                DELETE FROM newsletter.issue_delivery_queue
+                WHERE issue_id = $1 AND subscriber_id = $2
+            "#,
+        )
+        .await?;
+    transaction
+        .execute(&statement, &[&task.issue_id, &task.subscriber_id])
+        .await
+}
+
+async fn reschedule_task(
+    transaction: &Transaction<'_>,
+    task: &DeliveryTask,
+    n_retries: i32,
+    backoff: Duration,
+    last_error: &str,
+) -> Result<u64, tokio_postgres::Error> {
+    let backoff_ms = backoff.as_millis() as f64;
+    let statement = transaction
+        .prepare_cached(
+            r#"
+                UPDATE newsletter.issue_delivery_queue
+                SET n_retries = $3,
+                    execute_after = now() + make_interval(secs => $4::FLOAT8 / 1000),
+                    last_error = $5
+                WHERE issue_id = $1 AND subscriber_id = $2
+            "#,
+        )
+        .await?;
+    transaction
+        .execute(
+            &statement,
+            &[
+                &task.issue_id,
+                &task.subscriber_id,
+                &n_retries,
+                &backoff_ms,
+                &last_error,
+            ],
+        )
+        .await
+}
+
+async fn record_failure(
+    transaction: &Transaction<'_>,
+    task: &DeliveryTask,
+    n_retries: i32,
+    last_error: &str,
+) -> Result<u64, tokio_postgres::Error> {
+    let statement = transaction
+        .prepare_cached(
+            r#"
+                INSERT INTO newsletter.issue_delivery_failures
+                    (issue_id, subscriber_id, n_retries, last_error)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (issue_id, subscriber_id) DO UPDATE
+                    SET n_retries = $3, last_error = $4, failed_at = now()
+            "#,
+        )
+        .await?;
+    transaction
+        .execute(
+            &statement,
+            &[&task.issue_id, &task.subscriber_id, &n_retries, &last_error],
+        )
+        .await
+}
+
+// Counters are updated in a single statement so that concurrent workers finishing the last
+// tasks of an issue serialize on its row and exactly one of them marks it as completed
+async fn record_issue_progress(
+    transaction: &Transaction<'_>,
+    issue_id: Uuid,
+    delivered: i32,
+    failed: i32,
+) -> Result<u64, tokio_postgres::Error> {
+    let statement = transaction
+        .prepare_cached(
+            r#"
+                UPDATE newsletter.newsletter_issues
+                SET delivered = delivered + $2,
+                    failed = failed + $3,
+                    status = CASE WHEN delivered + failed + $2 + $3 >= total_recipients
+                        THEN 'completed' ELSE status END,
+                    completed_at = CASE WHEN delivered + failed + $2 + $3 >= total_recipients
+                        THEN now() ELSE completed_at END
+                WHERE id = $1
+            "#,
+        )
+        .await?;
+    transaction
+        .execute(&statement, &[&issue_id, &delivered, &failed])
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::DeliveryWorkerSettings;
+    use crate::issue_delivery_worker::retry_backoff;
+    use std::time::Duration;
+
+    #[test]
+    fn retry_backoff_doubles_until_maximum() {
+        let settings = DeliveryWorkerSettings {
+            initialbackoffms: 100,
+            maxbackoffms: 1000,
+            ..Default::default()
+        };
+        let backoffs: Vec<Duration> = (0..6).map(|n| retry_backoff(&settings, n)).collect();
+        assert_eq!(
+            vec![100, 200, 400, 800, 1000, 1000],
+            backoffs
+                .iter()
+                .map(|backoff| backoff.as_millis())
+                .collect::<Vec<u128>>()
+        );
+    }
+
+    #[test]
+    fn retry_backoff_does_not_overflow() {
+        let settings = DeliveryWorkerSettings {
+            initialbackoffms: u64::MAX / 2,
+            maxbackoffms: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(
+            Duration::from_millis(u64::MAX),
+            retry_backoff(&settings, i32::MAX)
+        );
+    }
+}
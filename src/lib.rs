@@ -1,4 +1,8 @@
+pub mod authentication;
+pub mod cli;
 pub mod configuration;
+pub mod email_client;
+pub mod issue_delivery_worker;
 pub mod postgres;
 pub mod readiness;
 pub mod routes;
@@ -3,27 +3,52 @@ use anyhow::{Context, Result};
 use deadpool_postgres::Pool;
 use futures::future;
 use newsletter_rs::{
-    configuration::{
-        get_configuration, DatabaseSettings, MigrationSettings, Settings, SslSettings,
+    authentication::{bootstrap_admin_user, DEFAULT_SESSION_TTL_SECONDS},
+    cli::{parse_args, run_migrate_command, Command, USAGE},
+    configuration::{get_configuration, DatabaseSettings, MigrationSettings, Settings},
+    email_client::{build_email_client, EmailClient},
+    issue_delivery_worker::{run_worker_until_stopped, UnsubscribeLinkSettings},
+    postgres::{
+        check_database_exists, generate_connection_pool, generate_replica_pool, migrate_database,
+        retry_startup, PostgresSetupError,
     },
-    postgres::{check_database_exists, generate_connection_pool, migrate_database},
     startup::run,
     telemetry,
 };
-use secrecy::SecretString;
 use std::net::TcpListener;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[actix_web::main]
 async fn main() -> Result<()> {
+    let command = match parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(usage) => {
+            eprintln!("{}", usage);
+            std::process::exit(2);
+        }
+    };
+    if command == Command::Help {
+        println!("{}", USAGE);
+        return Ok(());
+    }
     let subscriber_name = env!("CARGO_PKG_NAME");
     let env_filter = "info";
-    let subscriber = telemetry::get_subscriber(
-        subscriber_name.to_owned(),
-        env_filter.to_owned(),
-        std::io::stdout,
-    );
-    telemetry::init_subscriber(subscriber).with_context(|| format!("{}::main: Failed to initialize tracing subscriber with name '{}' and filter level '{}'", env!("CARGO_PKG_NAME"), subscriber_name, env_filter))?;
+    // Keep the standard output of subcommands for their report
+    let init_result = if command == Command::Serve {
+        telemetry::init_subscriber(telemetry::get_subscriber(
+            subscriber_name.to_owned(),
+            env_filter.to_owned(),
+            std::io::stdout,
+        ))
+    } else {
+        telemetry::init_subscriber(telemetry::get_subscriber(
+            subscriber_name.to_owned(),
+            env_filter.to_owned(),
+            std::io::stderr,
+        ))
+    };
+    init_result.with_context(|| format!("{}::main: Failed to initialize tracing subscriber with name '{}' and filter level '{}'", env!("CARGO_PKG_NAME"), subscriber_name, env_filter))?;
     let config_file: &str = "main.yaml";
     let configuration: Settings = get_configuration(config_file).unwrap_or_else(|error| {
         panic!(
@@ -31,7 +56,6 @@ async fn main() -> Result<()> {
             config_file, error
         )
     });
-    let connection_string = SecretString::from(configuration.database.connection_string());
     let database_name = match configuration.database.database.as_ref() {
         Some(database_name) => database_name.to_owned(),
         _ => {
@@ -50,42 +74,71 @@ async fn main() -> Result<()> {
         .map(|migrationsettings| MigrationSettings {
             migrate: migrationsettings.migrate,
             folder: migrationsettings.folder.to_owned(),
+            ondrift: migrationsettings.ondrift,
         });
     let database_settings = DatabaseSettings {
         port: configuration.database.port,
         host: configuration.database.host.to_owned(),
         username: configuration.database.username.to_owned(),
         password: configuration.database.password.to_owned(),
+        passwordfile: configuration.database.passwordfile.to_owned(),
         database: Some(database_name.to_owned()),
         migration: migration_settings,
-        ssl: SslSettings {
-            tls: configuration.database.ssl.tls,
-            cacertificates: configuration.database.ssl.cacertificates.to_owned(),
-        },
+        ssl: configuration.database.ssl.clone(),
+        startupretry: configuration.database.startupretry.clone(),
+        creation: configuration.database.creation.clone(),
+        pool: configuration.database.pool.clone(),
+        replicas: configuration.database.replicas.clone(),
+        candidatehosts: configuration.database.candidatehosts.clone(),
     };
-    let postgres_connection: Pool = match configuration.database.migration {
-        Some(ref migration) => {
-            if migration.migrate {
-                migrate_database(database_settings).await
-            } else {
-                generate_connection_pool(
-                    &connection_string,
-                    database_settings.ssl.tls,
-                    database_settings.ssl.cacertificates.as_ref(),
-                )?
+    if let Command::Migrate(ref migrate_command) = command {
+        match run_migrate_command(migrate_command, database_settings).await {
+            Ok(outcome) => {
+                print!("{}", outcome.report);
+                if !outcome.success {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+            Err(error) => {
+                eprintln!("ERROR: {:#}", error);
+                std::process::exit(1);
             }
         }
-        _ => generate_connection_pool(
-            &connection_string,
-            database_settings.ssl.tls,
-            database_settings.ssl.cacertificates.as_ref(),
-        )?,
+    }
+    tracing::info!(
+        "Connecting to Postgres at '{}'",
+        database_settings.connection()
+    );
+    let startup_retry = database_settings.startupretry.clone().unwrap_or_default();
+    let migrate = configuration
+        .database
+        .migration
+        .as_ref()
+        .is_some_and(|migration| migration.migrate);
+    let postgres_connection: Pool = if migrate {
+        retry_startup(&startup_retry, "migrating database", || {
+            migrate_database(database_settings.clone())
+        })
+        .await?
+    } else {
+        // Connections are opened lazily, waiting for Postgres is left to the existence check
+        generate_connection_pool(
+            database_settings.connection().build(),
+            &database_settings.ssl,
+            database_settings.pool.as_ref(),
+        )
+        .map_err(PostgresSetupError::Configuration)?
     };
-    let (database_exists, _) =
-        check_database_exists(database_name.as_str(), &configuration.database).await;
+    let (database_exists, _) = retry_startup(&startup_retry, "checking database existence", || {
+        check_database_exists(database_name.as_str(), &configuration.database)
+    })
+    .await?;
     if !database_exists {
-        panic!("[ERROR]: Database '{}' doesn't exist and the database_migration.migrate property was set to false", database_name.as_str());
+        return Err(PostgresSetupError::DatabaseMissing(database_name).into());
     }
+    let replica_pool =
+        generate_replica_pool(&database_settings).map_err(PostgresSetupError::Configuration)?;
     // Raises if failed to bind address
     let bind_address = (
         configuration.application.address.to_owned(),
@@ -99,9 +152,18 @@ async fn main() -> Result<()> {
             configuration.application.port
         )
     })?;
-    let mut admin_bind_address = None;
+    let mut admin_listener = None;
     if let Some(ref admin) = configuration.admin {
-        admin_bind_address = Some((admin.address.to_owned(), admin.port));
+        admin_listener = Some(
+            TcpListener::bind((admin.address.to_owned(), admin.port)).with_context(|| {
+                format!(
+                    "{}::main: Failed to open an admin TCP Listener on address '{}' and port '{}'.",
+                    env!("CARGO_PKG_NAME"),
+                    admin.address,
+                    admin.port
+                )
+            })?,
+        );
     }
     let health_cache_validity_ms: Option<Duration> =
         if configuration.application.healthcachevalidityms.is_some() {
@@ -115,17 +177,48 @@ async fn main() -> Result<()> {
         } else {
             None
         };
+    let mut admin_session_ttl = Duration::from_secs(DEFAULT_SESSION_TTL_SECONDS);
+    if let Some(ref admin) = configuration.admin {
+        if let Some(sessionttlseconds) = admin.sessionttlseconds {
+            admin_session_ttl = Duration::from_secs(sessionttlseconds);
+        }
+        if let Some(ref bootstrap) = admin.bootstrap {
+            bootstrap_admin_user(&postgres_connection, bootstrap).await?;
+        }
+    }
+    let email_client: Arc<dyn EmailClient> = build_email_client(configuration.email.as_ref())?;
+    // Newsletter deliveries are drained in the background, sharing the connection pool
+    let delivery_worker = run_worker_until_stopped(
+        Arc::new(postgres_connection.clone()),
+        email_client.clone(),
+        configuration.deliveryworker.clone().unwrap_or_default(),
+        UnsubscribeLinkSettings {
+            base_url: configuration.application.baseurl.to_owned(),
+            hmac_secret: configuration.application.hmacsecret.clone(),
+        },
+    );
     // Run server on TcpListener
     let (server1, server2): (Server, Option<Server>) = run(
         listener,
         postgres_connection,
-        admin_bind_address,
+        replica_pool,
+        admin_listener,
         health_cache_validity_ms,
+        email_client,
+        configuration.application.baseurl.to_owned(),
+        configuration.application.hmacsecret.clone(),
+        admin_session_ttl,
     )?;
-    if server2.is_some() {
-        future::try_join(server1, server2.unwrap()).await?;
-        return Ok(());
+    let servers = async {
+        if let Some(server2) = server2 {
+            future::try_join(server1, server2).await?;
+        } else {
+            server1.await?;
+        }
+        Ok::<(), anyhow::Error>(())
+    };
+    tokio::select! {
+        outcome = servers => outcome,
+        outcome = delivery_worker => outcome,
     }
-    server1.await?;
-    Ok(())
 }
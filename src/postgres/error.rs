@@ -0,0 +1,188 @@
+use deadpool_postgres::PoolError;
+use std::fmt;
+
+/// Failure while connecting to, creating or migrating the database, so that callers can tell
+/// transient connection problems from broken migrations.
+#[derive(Debug)]
+pub enum PostgresSetupError {
+    // Connection pool couldn't be built from the database settings
+    Configuration(anyhow::Error),
+    DatabaseNameMissing,
+    Connect {
+        server: String,
+        source: PoolError,
+    },
+    DatabaseMissing(String),
+    CreateDatabase {
+        database: String,
+        source: tokio_postgres::Error,
+    },
+    // Bookkeeping query failed, e.g. on the migrations table or its advisory lock
+    Query {
+        action: String,
+        source: tokio_postgres::Error,
+    },
+    ReadScripts {
+        path: String,
+        source: std::io::Error,
+    },
+    OrphanDownScript(String),
+    // Script ends the transaction wrapping it and its record
+    TransactionControl {
+        filename: String,
+        statement: String,
+    },
+    Migration {
+        filename: String,
+        source: tokio_postgres::Error,
+    },
+    RecordMigration {
+        filename: String,
+        source: tokio_postgres::Error,
+    },
+    ChecksumDrift(Vec<String>),
+    UnversionedMigration(String),
+    MissingScript(String),
+    IrreversibleMigration(String),
+}
+
+impl fmt::Display for PostgresSetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostgresSetupError::Configuration(_) => write!(f, "Invalid database configuration"),
+            PostgresSetupError::DatabaseNameMissing => {
+                write!(f, "No database name in the database settings")
+            }
+            PostgresSetupError::Connect { server, .. } => {
+                write!(f, "Failed to connect to Postgres at '{}'", server)
+            }
+            PostgresSetupError::DatabaseMissing(database) => write!(
+                f,
+                "Database '{}' doesn't exist and the database.migration.migrate property was set to false",
+                database
+            ),
+            PostgresSetupError::CreateDatabase { database, .. } => {
+                write!(f, "Failed to create database '{}'", database)
+            }
+            PostgresSetupError::Query { action, .. } => write!(f, "Failed to {}", action),
+            PostgresSetupError::ReadScripts { path, .. } => {
+                write!(f, "Failed to read migration scripts '{}'", path)
+            }
+            PostgresSetupError::OrphanDownScript(filename) => write!(
+                f,
+                "Down script '{}' has no matching '.up.sql' script",
+                filename
+            ),
+            PostgresSetupError::TransactionControl {
+                filename,
+                statement,
+            } => write!(
+                f,
+                "Migration '{}' runs '{}', which would commit it apart from its record. Remove it, or add the '-- migrate:no-transaction' marker to run the script on its own",
+                filename, statement
+            ),
+            PostgresSetupError::Migration { filename, .. } => {
+                write!(f, "Migration '{}' failed", filename)
+            }
+            PostgresSetupError::RecordMigration { filename, .. } => {
+                write!(f, "Failed to record migration '{}'", filename)
+            }
+            PostgresSetupError::ChecksumDrift(filenames) => write!(
+                f,
+                "Applied migrations were edited since they ran: {}. Review the changes and record the new checksums with 'migrate repair'",
+                filenames.join(", ")
+            ),
+            PostgresSetupError::UnversionedMigration(filename) => write!(
+                f,
+                "Migration '{}' has no version prefix to roll back to",
+                filename
+            ),
+            PostgresSetupError::MissingScript(filename) => write!(
+                f,
+                "Script of migration '{}' is missing from the migration scripts",
+                filename
+            ),
+            PostgresSetupError::IrreversibleMigration(filename) => write!(
+                f,
+                "Migration '{}' is forward-only, it has no '.down.sql' script",
+                filename
+            ),
+        }
+    }
+}
+
+// Underlying errors are only reachable through source(), format with {:#} through anyhow to
+// print the whole chain
+impl std::error::Error for PostgresSetupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PostgresSetupError::Configuration(error) => Some(error.as_ref()),
+            PostgresSetupError::Connect { source, .. } => Some(source),
+            PostgresSetupError::CreateDatabase { source, .. }
+            | PostgresSetupError::Query { source, .. }
+            | PostgresSetupError::Migration { source, .. }
+            | PostgresSetupError::RecordMigration { source, .. } => Some(source),
+            PostgresSetupError::ReadScripts { source, .. } => Some(source),
+            PostgresSetupError::DatabaseNameMissing
+            | PostgresSetupError::DatabaseMissing(_)
+            | PostgresSetupError::OrphanDownScript(_)
+            | PostgresSetupError::TransactionControl { .. }
+            | PostgresSetupError::ChecksumDrift(_)
+            | PostgresSetupError::UnversionedMigration(_)
+            | PostgresSetupError::MissingScript(_)
+            | PostgresSetupError::IrreversibleMigration(_) => None,
+        }
+    }
+}
+
+impl PostgresSetupError {
+    /// Connection failures may resolve by themselves, e.g. while Postgres is still starting,
+    /// unlike errors reported by the server for a statement.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            // Unlike a refused or dropped connection, rejected credentials won't resolve
+            PostgresSetupError::Connect {
+                source: PoolError::Backend(source),
+                ..
+            } => source.as_db_error().is_none(),
+            PostgresSetupError::Connect { .. } => true,
+            PostgresSetupError::CreateDatabase { source, .. }
+            | PostgresSetupError::Query { source, .. }
+            | PostgresSetupError::Migration { source, .. }
+            | PostgresSetupError::RecordMigration { source, .. } => source.as_db_error().is_none(),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::postgres::PostgresSetupError;
+    use deadpool_postgres::PoolError;
+    use std::error::Error;
+
+    #[test]
+    fn underlying_errors_are_sources() {
+        let error = PostgresSetupError::Connect {
+            server: "localhost:5432".to_owned(),
+            source: PoolError::Closed,
+        };
+        assert_eq!(
+            "Failed to connect to Postgres at 'localhost:5432'",
+            error.to_string()
+        );
+        assert!(error
+            .source()
+            .is_some_and(|source| source.is::<PoolError>()));
+        let error = PostgresSetupError::ReadScripts {
+            path: "migrations".to_owned(),
+            source: std::io::Error::from(std::io::ErrorKind::NotFound),
+        };
+        assert!(error
+            .source()
+            .is_some_and(|source| source.is::<std::io::Error>()));
+        assert!(format!("{:#}", anyhow::Error::new(error))
+            .starts_with("Failed to read migration scripts 'migrations': "));
+        assert!(PostgresSetupError::DatabaseNameMissing.source().is_none());
+    }
+}
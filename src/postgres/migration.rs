@@ -0,0 +1,1088 @@
+use crate::configuration::{DatabaseSettings, DriftPolicy};
+use crate::postgres::{create_database, run_simple_query, PostgresSetupError};
+use deadpool_postgres::{Object, Pool};
+use std::fmt;
+use std::fs::{read_dir, read_to_string};
+use time::OffsetDateTime;
+use tokio_postgres::types::ToSql;
+use uuid::Uuid;
+
+pub static MIGRATIONS_TABLE: &str = "_initialization_migrations";
+// Folder embedded into the binary at build time, see build.rs
+pub static DEFAULT_MIGRATIONS_FOLDER: &str = "migrations";
+// Key of the session advisory lock serializing migrators of the same database
+pub static MIGRATIONS_ADVISORY_LOCK_KEY: i64 = 0x6e65_7773_6c65_7474;
+// Line of a script that can't run inside a transaction, e.g. CREATE INDEX CONCURRENTLY
+pub static NO_TRANSACTION_MARKER: &str = "-- migrate:no-transaction";
+pub static UP_SCRIPT_SUFFIX: &str = ".up.sql";
+pub static DOWN_SCRIPT_SUFFIX: &str = ".down.sql";
+
+/// SQL script of the migrations folder, identified by the MD5 digest of its contents.
+#[derive(Clone, Debug)]
+pub struct MigrationScript {
+    pub filename: String,
+    pub contents: String,
+    pub md5_hash: Uuid,
+    // Run together with its bookkeeping statement in a single transaction
+    pub transactional: bool,
+    // Paired `.down.sql` script reverting a `.up.sql` one
+    pub down: Option<Box<MigrationScript>>,
+}
+
+impl MigrationScript {
+    pub fn new(filename: &str, contents: &str) -> Self {
+        let md5_script_digest = md5::compute(contents);
+        Self {
+            filename: filename.to_owned(),
+            contents: contents.to_owned(),
+            md5_hash: Uuid::from_bytes(md5_script_digest.0),
+            transactional: !contents
+                .lines()
+                .any(|line| line.trim() == NO_TRANSACTION_MARKER),
+            down: None,
+        }
+    }
+    pub fn with_down(mut self, down: MigrationScript) -> Self {
+        self.down = Some(Box::new(down));
+        self
+    }
+    pub fn down_md5_hash(&self) -> Option<Uuid> {
+        self.down.as_ref().map(|down| down.md5_hash)
+    }
+    /// Leading digits of the filename, e.g. the timestamp of `20220128124500_initialize.sql`.
+    pub fn version(&self) -> Option<i64> {
+        filename_version(&self.filename)
+    }
+    /// Contents to run inside the transaction wrapping the script and its record, without the
+    /// `BEGIN;` first line and `COMMIT;` last line of scripts written to run on their own.
+    /// Checksums keep covering the original contents.
+    ///
+    /// Any other statement controlling the transaction would end it early and record the
+    /// script separately, so the script is rejected instead.
+    pub fn transaction_body(&self) -> Result<String, PostgresSetupError> {
+        let mut lines: Vec<&str> = self.contents.lines().collect();
+        let is_code = |line: &&str| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with("--")
+        };
+        let first = lines.iter().position(is_code);
+        let last = lines.iter().rposition(is_code);
+        if let (Some(first), Some(last)) = (first, last) {
+            if first < last
+                && lines[first].trim().eq_ignore_ascii_case("BEGIN;")
+                && lines[last].trim().eq_ignore_ascii_case("COMMIT;")
+            {
+                lines.remove(last);
+                lines.remove(first);
+            }
+        }
+        let body = lines.join("\n");
+        if let Some(statement) = split_sql_statements(&body)
+            .into_iter()
+            .find(|statement| is_transaction_control(statement))
+        {
+            return Err(PostgresSetupError::TransactionControl {
+                filename: self.filename.to_owned(),
+                statement,
+            });
+        }
+        Ok(body)
+    }
+}
+
+/// Top-level statements of `sql`, without comments and with quoted strings, identifiers and
+/// dollar-quoted bodies like those of functions left out, enough to tell what they start with.
+fn split_sql_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut statement = String::new();
+    let mut rest = sql;
+    while let Some(character) = rest.chars().next() {
+        let skipped = if rest.starts_with("--") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with("/*") {
+            block_comment_length(rest)
+        } else if character == '\'' || character == '"' {
+            // Doubled quotes close and reopen the string, which skips them just as well
+            rest[1..].find(character).map_or(rest.len(), |end| end + 2)
+        } else if let Some(tag) = dollar_quote_tag(rest) {
+            rest[tag.len()..]
+                .find(tag)
+                .map_or(rest.len(), |end| tag.len() + end + tag.len())
+        } else {
+            0
+        };
+        if skipped > 0 {
+            statement.push(' ');
+            rest = &rest[skipped..];
+            continue;
+        }
+        if character == ';' {
+            statements.push(statement.trim().to_owned());
+            statement.clear();
+        } else {
+            statement.push(character);
+        }
+        rest = &rest[character.len_utf8()..];
+    }
+    statements.push(statement.trim().to_owned());
+    statements.retain(|statement| !statement.is_empty());
+    statements
+}
+
+// Length of the block comment at the start of `sql`, which nest in Postgres
+fn block_comment_length(sql: &str) -> usize {
+    let mut depth = 0;
+    let mut index = 0;
+    while index < sql.len() {
+        if sql[index..].starts_with("/*") {
+            depth += 1;
+            index += 2;
+        } else if sql[index..].starts_with("*/") {
+            depth -= 1;
+            index += 2;
+            if depth == 0 {
+                return index;
+            }
+        } else {
+            index += sql[index..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    sql.len()
+}
+
+// Opening `$$` or `$tag$` of a dollar-quoted string at the start of `sql`
+fn dollar_quote_tag(sql: &str) -> Option<&str> {
+    let end = sql.strip_prefix('$')?.find('$')?;
+    let valid_tag = sql[1..end + 1]
+        .chars()
+        .enumerate()
+        .all(|(index, tag_character)| {
+            tag_character == '_'
+                || tag_character.is_ascii_alphabetic()
+                || (index > 0 && tag_character.is_ascii_digit())
+        });
+    valid_tag.then(|| &sql[..end + 2])
+}
+
+/// Statements starting, ending or abandoning a transaction, savepoints are left alone.
+fn is_transaction_control(statement: &str) -> bool {
+    let words: Vec<String> = statement
+        .split_whitespace()
+        .take(3)
+        .map(|word| word.to_ascii_uppercase())
+        .collect();
+    let word = |index: usize| words.get(index).map(String::as_str);
+    match word(0) {
+        Some("BEGIN" | "COMMIT" | "END" | "ABORT") => true,
+        Some("START") => word(1) == Some("TRANSACTION"),
+        Some("ROLLBACK") => word(1) != Some("TO") && word(2) != Some("TO"),
+        _ => false,
+    }
+}
+
+/// Row of the migrations table.
+#[derive(Clone, Debug)]
+pub struct AppliedMigration {
+    pub version: i32,
+    pub filename: String,
+    pub installed_on: OffsetDateTime,
+    pub md5_hash: Uuid,
+    // None for forward-only migrations
+    pub down_md5_hash: Option<Uuid>,
+}
+
+/// Applied migration whose script was edited after it ran.
+#[derive(Clone, Debug)]
+pub struct DriftedMigration {
+    pub applied: AppliedMigration,
+    pub script: MigrationScript,
+}
+
+/// Comparison of the migrations folder against the migrations table, keyed by filename.
+#[derive(Debug, Default)]
+pub struct MigrationStatus {
+    pub applied: Vec<AppliedMigration>,
+    pub pending: Vec<MigrationScript>,
+    // Applied migrations whose script now has a different checksum
+    pub drifted: Vec<DriftedMigration>,
+    // Applied migrations whose script was removed from the folder
+    pub unknown: Vec<AppliedMigration>,
+}
+
+impl MigrationStatus {
+    pub fn new(applied: Vec<AppliedMigration>, scripts: Vec<MigrationScript>) -> Self {
+        let mut status = Self::default();
+        let filenames: Vec<String> = scripts
+            .iter()
+            .map(|script| script.filename.to_owned())
+            .collect();
+        for script in scripts {
+            // Latest row wins for filenames recorded more than once
+            match applied
+                .iter()
+                .rev()
+                .find(|migration| migration.filename == script.filename)
+            {
+                Some(migration)
+                    if migration.md5_hash == script.md5_hash
+                        && migration.down_md5_hash == script.down_md5_hash() => {}
+                Some(migration) => status.drifted.push(DriftedMigration {
+                    applied: migration.clone(),
+                    script,
+                }),
+                None => status.pending.push(script),
+            }
+        }
+        for migration in applied {
+            if status
+                .drifted
+                .iter()
+                .any(|drifted| drifted.applied.filename == migration.filename)
+            {
+                continue;
+            }
+            if filenames.contains(&migration.filename) {
+                status.applied.push(migration);
+            } else {
+                status.unknown.push(migration);
+            }
+        }
+        status
+    }
+    /// Every script of the folder is applied unchanged and every applied migration has its script.
+    pub fn is_verified(&self) -> bool {
+        self.pending.is_empty() && self.drifted.is_empty() && self.unknown.is_empty()
+    }
+}
+
+impl fmt::Display for MigrationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Applied migrations: {}", self.applied.len())?;
+        for migration in &self.applied {
+            writeln!(
+                f,
+                "  [applied] {:>4} {} ({})",
+                migration.version, migration.filename, migration.installed_on
+            )?;
+        }
+        writeln!(f, "Pending migrations: {}", self.pending.len())?;
+        for script in &self.pending {
+            writeln!(f, "  [pending]      {}", script.filename)?;
+        }
+        if !self.drifted.is_empty() {
+            writeln!(
+                f,
+                "Applied migrations edited since they ran: {}",
+                self.drifted.len()
+            )?;
+            for drifted in &self.drifted {
+                if drifted.applied.md5_hash == drifted.script.md5_hash {
+                    writeln!(
+                        f,
+                        "  [drifted] {:>4} {} (down script changed)",
+                        drifted.applied.version, drifted.applied.filename
+                    )?;
+                } else {
+                    writeln!(
+                        f,
+                        "  [drifted] {:>4} {} (md5 {} recorded, {} on disk)",
+                        drifted.applied.version,
+                        drifted.applied.filename,
+                        drifted.applied.md5_hash.simple(),
+                        drifted.script.md5_hash.simple()
+                    )?;
+                }
+            }
+        }
+        if !self.unknown.is_empty() {
+            writeln!(
+                f,
+                "Applied migrations without a matching script: {}",
+                self.unknown.len()
+            )?;
+            for migration in &self.unknown {
+                writeln!(
+                    f,
+                    "  [unknown] {:>4} {} (md5 {})",
+                    migration.version,
+                    migration.filename,
+                    migration.md5_hash.simple()
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn filename_version(filename: &str) -> Option<i64> {
+    let digits: String = filename
+        .chars()
+        .take_while(|character| character.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+/// Read the `.sql` scripts of `folder`, sorted by filename.
+///
+/// `.down.sql` scripts are paired with the `.up.sql` script of the same name instead of being
+/// listed on their own.
+pub fn read_migration_scripts(folder: &str) -> Result<Vec<MigrationScript>, PostgresSetupError> {
+    let read_folder_error = |source| PostgresSetupError::ReadScripts {
+        path: folder.to_owned(),
+        source,
+    };
+    let mut paths = Vec::new();
+    for entry in read_dir(folder).map_err(read_folder_error)? {
+        let path = entry.map_err(read_folder_error)?.path();
+        if path.is_file() && path.extension().is_some_and(|extension| extension == "sql") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let mut scripts = Vec::with_capacity(paths.len());
+    for path in paths {
+        let contents = read_to_string(&path).map_err(|source| PostgresSetupError::ReadScripts {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        scripts.push(MigrationScript::new(&filename, &contents));
+    }
+    group_migration_scripts(scripts)
+}
+
+// (filename, contents) of the migrations folder at build time, generated by build.rs
+include!(concat!(env!("OUT_DIR"), "/embedded_migrations.rs"));
+
+/// Scripts of the migrations folder embedded into the binary at build time, sorted by filename.
+pub fn embedded_migration_scripts() -> Result<Vec<MigrationScript>, PostgresSetupError> {
+    group_migration_scripts(
+        EMBEDDED_MIGRATIONS
+            .iter()
+            .map(|(filename, contents)| MigrationScript::new(filename, contents))
+            .collect(),
+    )
+}
+
+/// Scripts of the folder overriding the embedded ones when configured, else the embedded ones.
+pub fn load_migration_scripts(
+    database_settings: &DatabaseSettings,
+) -> Result<Vec<MigrationScript>, PostgresSetupError> {
+    match database_settings
+        .migration
+        .as_ref()
+        .and_then(|migration| migration.folder.as_ref())
+    {
+        Some(folder) => {
+            tracing::info!(
+                "{}::postgres::load_migration_scripts: Reading migrations from folder '{}'",
+                env!("CARGO_PKG_NAME"),
+                folder
+            );
+            read_migration_scripts(folder)
+        }
+        None => embedded_migration_scripts(),
+    }
+}
+
+fn group_migration_scripts(
+    scripts: Vec<MigrationScript>,
+) -> Result<Vec<MigrationScript>, PostgresSetupError> {
+    let (down_scripts, scripts) = scripts
+        .into_iter()
+        .partition(|script| script.filename.ends_with(DOWN_SCRIPT_SUFFIX));
+    let scripts = pair_down_scripts(scripts, down_scripts)?;
+    // Fail before applying anything rather than on reaching the script
+    for script in &scripts {
+        for script in std::iter::once(script).chain(script.down.as_deref()) {
+            if script.transactional {
+                script.transaction_body()?;
+            }
+        }
+    }
+    Ok(scripts)
+}
+
+fn pair_down_scripts(
+    scripts: Vec<MigrationScript>,
+    mut down_scripts: Vec<MigrationScript>,
+) -> Result<Vec<MigrationScript>, PostgresSetupError> {
+    let mut paired = Vec::with_capacity(scripts.len());
+    for script in scripts {
+        let down_filename = script
+            .filename
+            .strip_suffix(UP_SCRIPT_SUFFIX)
+            .map(|stem| format!("{}{}", stem, DOWN_SCRIPT_SUFFIX));
+        match down_scripts
+            .iter()
+            .position(|down| Some(&down.filename) == down_filename.as_ref())
+        {
+            Some(index) => paired.push(script.with_down(down_scripts.remove(index))),
+            None => paired.push(script),
+        }
+    }
+    if let Some(orphan) = down_scripts.first() {
+        return Err(PostgresSetupError::OrphanDownScript(
+            orphan.filename.to_owned(),
+        ));
+    }
+    Ok(paired)
+}
+
+fn drift_policy(database_settings: &DatabaseSettings) -> DriftPolicy {
+    database_settings
+        .migration
+        .as_ref()
+        .map_or(DriftPolicy::default(), |migration| migration.ondrift)
+}
+
+#[tracing::instrument(name = "Creating migrations table.", skip(postgres_client))]
+pub async fn create_migrations_table(postgres_client: &Object) -> Result<(), PostgresSetupError> {
+    let create_table_statement = format!(
+        "CREATE TABLE IF NOT EXISTS {}(
+        version SERIAL PRIMARY KEY,
+        filename TEXT NOT NULL,
+        installed_on TIMESTAMPTZ NOT NULL DEFAULT now(),
+        md5_hash UUID NOT NULL,
+        down_md5_hash UUID
+    );
+    ALTER TABLE {} ADD COLUMN IF NOT EXISTS down_md5_hash UUID;",
+        MIGRATIONS_TABLE, MIGRATIONS_TABLE
+    );
+    run_simple_query(postgres_client, &create_table_statement)
+        .await
+        .map_err(|source| PostgresSetupError::Query {
+            action: format!("create table '{}'", MIGRATIONS_TABLE),
+            source,
+        })?;
+    Ok(())
+}
+
+/// Rows of the migrations table, none when the database was never migrated.
+#[tracing::instrument(name = "Querying applied migrations.", skip(postgres_client))]
+pub async fn get_applied_migrations(
+    postgres_client: &Object,
+) -> Result<Vec<AppliedMigration>, PostgresSetupError> {
+    let query_error = |source| PostgresSetupError::Query {
+        action: format!("query table '{}'", MIGRATIONS_TABLE),
+        source,
+    };
+    let table_exists: bool = postgres_client
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&MIGRATIONS_TABLE])
+        .await
+        .map_err(query_error)?
+        .get(0);
+    if !table_exists {
+        return Ok(Vec::new());
+    }
+    // Tables created before down scripts were supported lack the column until the next migration
+    let has_down_column: bool = postgres_client
+        .query_one(
+            "SELECT EXISTS (SELECT 1 FROM information_schema.columns WHERE table_name = $1 AND column_name = 'down_md5_hash')",
+            &[&MIGRATIONS_TABLE],
+        )
+        .await
+        .map_err(query_error)?
+        .get(0);
+    let down_column = if has_down_column {
+        "down_md5_hash"
+    } else {
+        "NULL::UUID AS down_md5_hash"
+    };
+    let rows = postgres_client
+        .query(
+            &format!(
+                "SELECT version, filename, installed_on, md5_hash, {} FROM {} ORDER BY version",
+                down_column, MIGRATIONS_TABLE
+            ),
+            &[],
+        )
+        .await
+        .map_err(query_error)?;
+    Ok(rows
+        .iter()
+        .map(|row| AppliedMigration {
+            version: row.get("version"),
+            filename: row.get("filename"),
+            installed_on: row.get("installed_on"),
+            md5_hash: row.get("md5_hash"),
+            down_md5_hash: row.get("down_md5_hash"),
+        })
+        .collect())
+}
+
+/// Compare the migration scripts of `database_settings` against the database.
+#[tracing::instrument(name = "Checking migration status.", skip(postgres_client))]
+pub async fn get_migration_status(
+    postgres_client: &Object,
+    database_settings: &DatabaseSettings,
+) -> Result<MigrationStatus, PostgresSetupError> {
+    let scripts = load_migration_scripts(database_settings)?;
+    let applied = get_applied_migrations(postgres_client).await?;
+    Ok(MigrationStatus::new(applied, scripts))
+}
+
+/// Run `script` followed by its bookkeeping statement, atomically in a transaction unless the
+/// script carries the no-transaction marker.
+async fn execute_script(
+    postgres_client: &mut Object,
+    script: &MigrationScript,
+    bookkeeping_statement: &str,
+    bookkeeping_params: &[&(dyn ToSql + Sync)],
+) -> Result<(), PostgresSetupError> {
+    let failed_error = |source| PostgresSetupError::Migration {
+        filename: script.filename.to_owned(),
+        source,
+    };
+    let record_error = |source| PostgresSetupError::RecordMigration {
+        filename: script.filename.to_owned(),
+        source,
+    };
+    if script.transactional {
+        // Dropping the transaction on error rolls both the script and its record back
+        let transaction = postgres_client.transaction().await.map_err(failed_error)?;
+        transaction
+            .batch_execute(&script.transaction_body()?)
+            .await
+            .map_err(failed_error)?;
+        transaction
+            .execute(bookkeeping_statement, bookkeeping_params)
+            .await
+            .map_err(record_error)?;
+        transaction.commit().await.map_err(failed_error)?;
+    } else {
+        tracing::warn!(
+            "{}::postgres::execute_script: Migration '{}' runs outside of a transaction",
+            env!("CARGO_PKG_NAME"),
+            script.filename
+        );
+        run_simple_query(postgres_client, &script.contents)
+            .await
+            .map_err(failed_error)?;
+        postgres_client
+            .execute(bookkeeping_statement, bookkeeping_params)
+            .await
+            .map_err(record_error)?;
+    }
+    Ok(())
+}
+
+/// Run `scripts` in order, each of them atomically with its record in the migrations table.
+///
+/// Scripts carrying the no-transaction marker run on their own and are recorded afterwards.
+#[tracing::instrument(name = "Applying migrations.", skip(postgres_client, scripts))]
+pub async fn apply_migrations(
+    postgres_client: &mut Object,
+    scripts: &[MigrationScript],
+) -> Result<(), PostgresSetupError> {
+    let insert_migration_statement = format!(
+        "INSERT INTO {} (filename, md5_hash, down_md5_hash) VALUES ($1, $2, $3)",
+        MIGRATIONS_TABLE
+    );
+    for script in scripts {
+        tracing::info!(
+            "{}::postgres::apply_migrations: Applying migration '{}'",
+            env!("CARGO_PKG_NAME"),
+            script.filename
+        );
+        execute_script(
+            postgres_client,
+            script,
+            &insert_migration_statement,
+            &[&script.filename, &script.md5_hash, &script.down_md5_hash()],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Run the down scripts of `scripts` in order, each of them atomically with the removal of its
+/// record from the migrations table.
+#[tracing::instrument(name = "Reverting migrations.", skip(postgres_client, scripts))]
+pub async fn revert_migrations(
+    postgres_client: &mut Object,
+    scripts: &[MigrationScript],
+) -> Result<(), PostgresSetupError> {
+    let delete_migration_statement =
+        format!("DELETE FROM {} WHERE filename = $1", MIGRATIONS_TABLE);
+    for script in scripts {
+        let down = script
+            .down
+            .as_ref()
+            .ok_or_else(|| PostgresSetupError::IrreversibleMigration(script.filename.to_owned()))?;
+        tracing::info!(
+            "{}::postgres::revert_migrations: Reverting migration '{}' with '{}'",
+            env!("CARGO_PKG_NAME"),
+            script.filename,
+            down.filename
+        );
+        execute_script(
+            postgres_client,
+            down,
+            &delete_migration_statement,
+            &[&script.filename],
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Hold the migrations advisory lock of the database, waiting for concurrent migrators.
+#[tracing::instrument(name = "Locking migrations.", skip(postgres_client))]
+pub async fn lock_migrations(postgres_client: &Object) -> Result<(), PostgresSetupError> {
+    postgres_client
+        .execute(
+            "SELECT pg_advisory_lock($1)",
+            &[&MIGRATIONS_ADVISORY_LOCK_KEY],
+        )
+        .await
+        .map_err(|source| PostgresSetupError::Query {
+            action: "acquire migrations advisory lock".to_owned(),
+            source,
+        })?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Unlocking migrations.", skip(postgres_client))]
+pub async fn unlock_migrations(postgres_client: &Object) -> Result<(), PostgresSetupError> {
+    postgres_client
+        .execute(
+            "SELECT pg_advisory_unlock($1)",
+            &[&MIGRATIONS_ADVISORY_LOCK_KEY],
+        )
+        .await
+        .map_err(|source| PostgresSetupError::Query {
+            action: "release migrations advisory lock".to_owned(),
+            source,
+        })?;
+    Ok(())
+}
+
+/// Release the migrations advisory lock after `outcome` of the work done under it. A failure
+/// to unlock is only logged when the work already failed, which is the error worth reporting.
+async fn unlock_migrations_after<T>(
+    postgres_client: &Object,
+    outcome: Result<T, PostgresSetupError>,
+) -> Result<T, PostgresSetupError> {
+    let unlocked = unlock_migrations(postgres_client).await;
+    match outcome {
+        Ok(value) => unlocked.map(|_| value),
+        Err(error) => {
+            if let Err(unlock_error) = unlocked {
+                tracing::warn!(
+                    "{}::postgres::unlock_migrations_after: {:#}",
+                    env!("CARGO_PKG_NAME"),
+                    anyhow::Error::new(unlock_error)
+                );
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Create the database when missing and apply pending migrations, returning a pool to it.
+#[tracing::instrument(name = "Migrating Database.")]
+pub async fn migrate_database(
+    mut database_settings: DatabaseSettings,
+) -> Result<Pool, PostgresSetupError> {
+    let postgres_pool = create_database(&mut database_settings).await?;
+    let mut postgres_client =
+        postgres_pool
+            .get()
+            .await
+            .map_err(|source| PostgresSetupError::Connect {
+                server: database_settings.connection().to_string(),
+                source,
+            })?;
+    run_pending_migrations(&mut postgres_client, &database_settings).await?;
+    Ok(postgres_pool)
+}
+
+/// Apply the pending migrations under the migrations advisory lock, returning the status found
+/// once the lock was acquired.
+#[tracing::instrument(name = "Running pending migrations.", skip(postgres_client))]
+pub async fn run_pending_migrations(
+    postgres_client: &mut Object,
+    database_settings: &DatabaseSettings,
+) -> Result<MigrationStatus, PostgresSetupError> {
+    lock_migrations(postgres_client).await?;
+    let outcome = run_pending_migrations_locked(postgres_client, database_settings).await;
+    // The lock is released with the session too, should the connection have failed
+    unlock_migrations_after(postgres_client, outcome).await
+}
+
+async fn run_pending_migrations_locked(
+    postgres_client: &mut Object,
+    database_settings: &DatabaseSettings,
+) -> Result<MigrationStatus, PostgresSetupError> {
+    create_migrations_table(postgres_client).await?;
+    let status = get_migration_status(postgres_client, database_settings).await?;
+    check_drift(&status.drifted, database_settings)?;
+    apply_migrations(postgres_client, &status.pending).await?;
+    Ok(status)
+}
+
+/// Refuse or only log edited migrations, according to the configured drift policy.
+fn check_drift(
+    drifted: &[DriftedMigration],
+    database_settings: &DatabaseSettings,
+) -> Result<(), PostgresSetupError> {
+    if drifted.is_empty() {
+        return Ok(());
+    }
+    let filenames: Vec<&str> = drifted
+        .iter()
+        .map(|drifted| drifted.applied.filename.as_str())
+        .collect();
+    match drift_policy(database_settings) {
+        DriftPolicy::Fail => {
+            return Err(PostgresSetupError::ChecksumDrift(
+                filenames
+                    .iter()
+                    .map(|filename| filename.to_string())
+                    .collect(),
+            ))
+        }
+        DriftPolicy::Warn => tracing::warn!(
+            "{}::postgres::check_drift: Applied migrations were edited since they ran: {}",
+            env!("CARGO_PKG_NAME"),
+            filenames.join(", ")
+        ),
+    }
+    Ok(())
+}
+
+/// Scripts to revert, latest applied first, so that only migrations up to `to` remain applied.
+///
+/// Fails without reverting anything when one of them has no down script.
+pub fn plan_rollback(
+    applied: &[AppliedMigration],
+    scripts: &[MigrationScript],
+    to: i64,
+) -> Result<Vec<MigrationScript>, PostgresSetupError> {
+    let mut plan: Vec<MigrationScript> = Vec::new();
+    for migration in applied.iter().rev() {
+        match filename_version(&migration.filename) {
+            Some(version) if version <= to => continue,
+            Some(_) => {}
+            None => {
+                return Err(PostgresSetupError::UnversionedMigration(
+                    migration.filename.to_owned(),
+                ))
+            }
+        }
+        // Filenames recorded more than once are reverted once
+        if plan
+            .iter()
+            .any(|script| script.filename == migration.filename)
+        {
+            continue;
+        }
+        let script = scripts
+            .iter()
+            .find(|script| script.filename == migration.filename)
+            .ok_or_else(|| PostgresSetupError::MissingScript(migration.filename.to_owned()))?;
+        if script.down.is_none() {
+            return Err(PostgresSetupError::IrreversibleMigration(
+                migration.filename.to_owned(),
+            ));
+        }
+        plan.push(script.clone());
+    }
+    Ok(plan)
+}
+
+/// Revert applied migrations with a version greater than `to` under the migrations advisory
+/// lock, returning the reverted scripts.
+#[tracing::instrument(name = "Rolling back migrations.", skip(postgres_client))]
+pub async fn rollback_migrations(
+    postgres_client: &mut Object,
+    database_settings: &DatabaseSettings,
+    to: i64,
+) -> Result<Vec<MigrationScript>, PostgresSetupError> {
+    lock_migrations(postgres_client).await?;
+    let outcome = rollback_migrations_locked(postgres_client, database_settings, to).await;
+    unlock_migrations_after(postgres_client, outcome).await
+}
+
+async fn rollback_migrations_locked(
+    postgres_client: &mut Object,
+    database_settings: &DatabaseSettings,
+    to: i64,
+) -> Result<Vec<MigrationScript>, PostgresSetupError> {
+    create_migrations_table(postgres_client).await?;
+    let scripts = load_migration_scripts(database_settings)?;
+    let applied = get_applied_migrations(postgres_client).await?;
+    let plan = plan_rollback(&applied, &scripts, to)?;
+    let status = MigrationStatus::new(applied, scripts);
+    let drifted: Vec<DriftedMigration> = status
+        .drifted
+        .into_iter()
+        .filter(|drifted| {
+            plan.iter()
+                .any(|script| script.filename == drifted.applied.filename)
+        })
+        .collect();
+    check_drift(&drifted, database_settings)?;
+    revert_migrations(postgres_client, &plan).await?;
+    Ok(plan)
+}
+
+/// Record the current checksum of edited scripts, without running them again, returning them.
+///
+/// Drift is read again under the migrations advisory lock, and every checksum is recorded in a
+/// single transaction, so that a migrator booting meanwhile isn't overwritten.
+#[tracing::instrument(name = "Repairing migration checksums.", skip(postgres_client))]
+pub async fn repair_migration_checksums(
+    postgres_client: &mut Object,
+    database_settings: &DatabaseSettings,
+) -> Result<Vec<DriftedMigration>, PostgresSetupError> {
+    lock_migrations(postgres_client).await?;
+    let outcome = repair_migration_checksums_locked(postgres_client, database_settings).await;
+    unlock_migrations_after(postgres_client, outcome).await
+}
+
+async fn repair_migration_checksums_locked(
+    postgres_client: &mut Object,
+    database_settings: &DatabaseSettings,
+) -> Result<Vec<DriftedMigration>, PostgresSetupError> {
+    let drifted = get_migration_status(postgres_client, database_settings)
+        .await?
+        .drifted;
+    let query_error = |source| PostgresSetupError::Query {
+        action: "update migration checksums".to_owned(),
+        source,
+    };
+    let transaction = postgres_client.transaction().await.map_err(query_error)?;
+    for migration in &drifted {
+        tracing::info!(
+            "{}::postgres::repair_migration_checksums: Recording checksum {} for migration '{}'",
+            env!("CARGO_PKG_NAME"),
+            migration.script.md5_hash.simple(),
+            migration.applied.filename
+        );
+        transaction
+            .execute(
+                &format!(
+                    "UPDATE {} SET md5_hash = $1, down_md5_hash = $2 WHERE filename = $3",
+                    MIGRATIONS_TABLE
+                ),
+                &[
+                    &migration.script.md5_hash,
+                    &migration.script.down_md5_hash(),
+                    &migration.applied.filename,
+                ],
+            )
+            .await
+            .map_err(|source| PostgresSetupError::Query {
+                action: format!(
+                    "update checksum of migration '{}'",
+                    migration.applied.filename
+                ),
+                source,
+            })?;
+    }
+    transaction.commit().await.map_err(query_error)?;
+    Ok(drifted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{group_migration_scripts, pair_down_scripts};
+    use crate::postgres::{
+        embedded_migration_scripts, plan_rollback, read_migration_scripts, AppliedMigration,
+        MigrationScript, MigrationStatus, PostgresSetupError, DEFAULT_MIGRATIONS_FOLDER,
+    };
+    use time::OffsetDateTime;
+
+    fn applied(version: i32, script: &MigrationScript) -> AppliedMigration {
+        AppliedMigration {
+            version,
+            filename: script.filename.to_owned(),
+            installed_on: OffsetDateTime::UNIX_EPOCH,
+            md5_hash: script.md5_hash,
+            down_md5_hash: script.down_md5_hash(),
+        }
+    }
+
+    #[test]
+    fn md5_hash_matches_digest_of_contents() {
+        let script = MigrationScript::new("1_create.sql", "SELECT 1;");
+        assert_eq!(
+            format!("{:x}", md5::compute("SELECT 1;")),
+            script.md5_hash.simple().to_string()
+        );
+    }
+
+    #[test]
+    fn no_transaction_marker_opts_out_of_transaction() {
+        let script = MigrationScript::new("1_create.sql", "CREATE TABLE a();");
+        assert!(script.transactional);
+        let script = MigrationScript::new(
+            "2_index.sql",
+            "-- migrate:no-transaction\nCREATE INDEX CONCURRENTLY a_idx ON a(b);",
+        );
+        assert!(!script.transactional);
+    }
+
+    #[test]
+    fn transaction_body_drops_own_transaction_statements() {
+        let script = MigrationScript::new(
+            "1_create.sql",
+            "BEGIN;\nCREATE FUNCTION f() RETURNS VOID AS $$\n    BEGIN\n    END;\n$$ LANGUAGE plpgsql;\ncommit;\n",
+        );
+        assert_eq!(
+            "CREATE FUNCTION f() RETURNS VOID AS $$\n    BEGIN\n    END;\n$$ LANGUAGE plpgsql;",
+            script.transaction_body().unwrap()
+        );
+        let script = MigrationScript::new(
+            "2_create.sql",
+            "-- Comment\nBEGIN;\nSAVEPOINT a;\nROLLBACK TO SAVEPOINT a;\nDO $body$ BEGIN END $body$;\nSELECT 'end;';\n/* commit; */\nCOMMIT;\n-- Done\n",
+        );
+        assert!(script.transaction_body().is_ok());
+    }
+
+    #[test]
+    fn transaction_control_statements_are_rejected() {
+        for contents in [
+            "BEGIN TRANSACTION;\nCREATE TABLE a();\nCOMMIT;",
+            "START TRANSACTION;\nCREATE TABLE a();\nCOMMIT;",
+            "BEGIN;\nCREATE TABLE a();\nEND;",
+            "BEGIN;\nCREATE TABLE a();\nCOMMIT WORK;",
+            "BEGIN; CREATE TABLE a();\nCOMMIT;",
+            "BEGIN;\nCREATE TABLE a();\nCOMMIT;\nCREATE TABLE b();",
+            "CREATE TABLE a();\nROLLBACK;",
+            "CREATE TABLE a(); abort;",
+        ] {
+            let script = MigrationScript::new("1_create.sql", contents);
+            assert!(
+                matches!(
+                    script.transaction_body(),
+                    Err(PostgresSetupError::TransactionControl { .. })
+                ),
+                "Script '{}' should be rejected",
+                contents
+            );
+        }
+        let script = MigrationScript::new(
+            "2_index.sql",
+            "-- migrate:no-transaction\nBEGIN TRANSACTION;\nCREATE TABLE a();\nCOMMIT;",
+        );
+        assert!(group_migration_scripts(vec![script]).is_ok());
+        let script = MigrationScript::new("3_create.sql", "START TRANSACTION;");
+        assert!(group_migration_scripts(vec![script]).is_err());
+    }
+
+    #[test]
+    fn unapplied_scripts_are_pending() {
+        let first = MigrationScript::new("1_create.sql", "CREATE TABLE a();");
+        let second = MigrationScript::new("2_alter.sql", "ALTER TABLE a ADD b INT;");
+        let status = MigrationStatus::new(vec![applied(1, &first)], vec![first, second]);
+        assert_eq!(1, status.applied.len());
+        assert_eq!(vec!["2_alter.sql"], pending_filenames(&status));
+        assert!(!status.is_verified());
+    }
+
+    #[test]
+    fn edited_scripts_are_drifted_not_pending() {
+        let original = MigrationScript::new("1_create.sql", "CREATE TABLE a();");
+        let edited = MigrationScript::new("1_create.sql", "CREATE TABLE a(b INT);");
+        let status = MigrationStatus::new(vec![applied(1, &original)], vec![edited.clone()]);
+        assert_eq!(1, status.drifted.len());
+        assert_eq!(edited.md5_hash, status.drifted[0].script.md5_hash);
+        assert!(status.pending.is_empty());
+        assert!(status.unknown.is_empty());
+        assert!(!status.is_verified());
+    }
+
+    #[test]
+    fn removed_scripts_are_unknown() {
+        let script = MigrationScript::new("1_create.sql", "CREATE TABLE a();");
+        let status = MigrationStatus::new(vec![applied(1, &script)], Vec::new());
+        assert_eq!(1, status.unknown.len());
+        assert!(!status.is_verified());
+    }
+
+    #[test]
+    fn fully_applied_folder_is_verified() {
+        let script = MigrationScript::new("1_create.sql", "CREATE TABLE a();");
+        let status = MigrationStatus::new(vec![applied(1, &script)], vec![script]);
+        assert!(status.is_verified());
+    }
+
+    #[test]
+    fn down_scripts_are_paired_with_up_scripts() {
+        let scripts = pair_down_scripts(
+            vec![
+                MigrationScript::new("1_create.sql", "CREATE TABLE a();"),
+                MigrationScript::new("2_alter.up.sql", "ALTER TABLE a ADD b INT;"),
+            ],
+            vec![MigrationScript::new(
+                "2_alter.down.sql",
+                "ALTER TABLE a DROP b;",
+            )],
+        )
+        .unwrap();
+        assert_eq!(2, scripts.len());
+        assert!(scripts[0].down.is_none());
+        assert_eq!(
+            "2_alter.down.sql",
+            scripts[1].down.as_ref().unwrap().filename
+        );
+        assert!(pair_down_scripts(
+            Vec::new(),
+            vec![MigrationScript::new("3_orphan.down.sql", "SELECT 1;")]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn edited_down_scripts_are_drifted() {
+        let down = MigrationScript::new("1_create.down.sql", "DROP TABLE a;");
+        let script = MigrationScript::new("1_create.up.sql", "CREATE TABLE a();").with_down(down);
+        let edited = script.clone().with_down(MigrationScript::new(
+            "1_create.down.sql",
+            "DROP TABLE IF EXISTS a;",
+        ));
+        let status = MigrationStatus::new(vec![applied(1, &script)], vec![edited]);
+        assert_eq!(1, status.drifted.len());
+    }
+
+    #[test]
+    fn rollback_reverts_later_versions_latest_first() {
+        let first = MigrationScript::new("10_create.sql", "CREATE TABLE a();");
+        let second = MigrationScript::new("20_alter.up.sql", "ALTER TABLE a ADD b INT;").with_down(
+            MigrationScript::new("20_alter.down.sql", "ALTER TABLE a DROP b;"),
+        );
+        let third = MigrationScript::new("30_alter.up.sql", "ALTER TABLE a ADD c INT;").with_down(
+            MigrationScript::new("30_alter.down.sql", "ALTER TABLE a DROP c;"),
+        );
+        let applied = vec![applied(1, &first), applied(2, &second), applied(3, &third)];
+        let scripts = vec![first, second, third];
+        let plan = plan_rollback(&applied, &scripts, 10).unwrap();
+        let filenames: Vec<&str> = plan.iter().map(|script| script.filename.as_str()).collect();
+        assert_eq!(vec!["30_alter.up.sql", "20_alter.up.sql"], filenames);
+        assert!(plan_rollback(&applied, &scripts, 30).unwrap().is_empty());
+        // Forward-only migrations can't be reverted
+        assert!(plan_rollback(&applied, &scripts, 0).is_err());
+    }
+
+    #[test]
+    fn embedded_scripts_match_migrations_folder() {
+        let embedded = embedded_migration_scripts().unwrap();
+        let on_disk = read_migration_scripts(DEFAULT_MIGRATIONS_FOLDER).unwrap();
+        assert_eq!(on_disk.len(), embedded.len());
+        for (embedded, on_disk) in embedded.iter().zip(on_disk.iter()) {
+            assert_eq!(on_disk.filename, embedded.filename);
+            assert_eq!(on_disk.md5_hash, embedded.md5_hash);
+        }
+    }
+
+    fn pending_filenames(status: &MigrationStatus) -> Vec<&str> {
+        status
+            .pending
+            .iter()
+            .map(|script| script.filename.as_str())
+            .collect()
+    }
+}
@@ -0,0 +1,300 @@
+mod error;
+mod migration;
+mod quote;
+mod retry;
+mod router;
+mod tls;
+
+pub use error::*;
+pub use migration::*;
+pub use quote::*;
+pub use retry::*;
+pub use router::*;
+pub use tls::*;
+
+use crate::configuration::{
+    DatabaseCreationSettings, DatabaseSettings, PoolRecyclingMethod, PoolSettings, SslMode,
+    SslSettings,
+};
+use anyhow::{Context, Error, Result};
+use deadpool_postgres::{
+    Manager, ManagerConfig, Object, Pool, PoolError, RecyclingMethod, Runtime,
+};
+use postgres_native_tls::MakeTlsConnector;
+use std::time::Duration;
+use tokio_postgres::config::Host;
+use tokio_postgres::{NoTls, SimpleQueryMessage};
+use tracing::info;
+
+pub static DEFAULT_POOL_MAX_SIZE: usize = 16;
+
+/// Apply the session settings of `pool_settings` to connections opened with `postgres_configuration`.
+pub fn apply_pool_settings(
+    postgres_configuration: &mut tokio_postgres::Config,
+    pool_settings: &PoolSettings,
+) {
+    if let Some(ref application_name) = pool_settings.applicationname {
+        postgres_configuration.application_name(application_name);
+    }
+    if let Some(statement_timeout_ms) = pool_settings.statementtimeoutms {
+        postgres_configuration.options(format!("-c statement_timeout={}", statement_timeout_ms));
+    }
+    if let Some(connect_timeout_ms) = pool_settings.connecttimeoutms {
+        postgres_configuration.connect_timeout(Duration::from_millis(connect_timeout_ms));
+    }
+    if let Some(keepalives) = pool_settings.keepalives {
+        postgres_configuration.keepalives(keepalives);
+    }
+    if let Some(keepalives_idle_secs) = pool_settings.keepalivesidlesecs {
+        postgres_configuration.keepalives_idle(Duration::from_secs(keepalives_idle_secs));
+    }
+}
+
+fn recycling_method(pool_settings: &PoolSettings) -> RecyclingMethod {
+    match pool_settings.recyclingmethod {
+        PoolRecyclingMethod::Fast => RecyclingMethod::Fast,
+        PoolRecyclingMethod::Verified => RecyclingMethod::Verified,
+        PoolRecyclingMethod::Clean => RecyclingMethod::Clean,
+    }
+}
+
+fn build_pool(deadpool_manager: Manager, pool_settings: &PoolSettings) -> Result<Pool, Error> {
+    Pool::builder(deadpool_manager)
+        .max_size(pool_settings.maxsize.unwrap_or(DEFAULT_POOL_MAX_SIZE))
+        .wait_timeout(pool_settings.waittimeoutms.map(Duration::from_millis))
+        .create_timeout(pool_settings.createtimeoutms.map(Duration::from_millis))
+        .recycle_timeout(pool_settings.recycletimeoutms.map(Duration::from_millis))
+        // Timeouts need a runtime to be enforced
+        .runtime(Runtime::Tokio1)
+        .build()
+        .with_context(|| {
+            format!(
+                "{}::postgres::generate_connection_pool: Failed to build connection pool to postgres",
+                env!("CARGO_PKG_NAME")
+            )
+        })
+}
+
+#[tracing::instrument(
+    name = "Generating database connection pool.",
+    skip(postgres_configuration)
+)]
+pub fn generate_connection_pool(
+    mut postgres_configuration: tokio_postgres::Config,
+    ssl_settings: &SslSettings,
+    pool_settings: Option<&PoolSettings>,
+) -> Result<Pool, Error> {
+    let default_pool_settings = PoolSettings::default();
+    let pool_settings = pool_settings.unwrap_or(&default_pool_settings);
+    apply_pool_settings(&mut postgres_configuration, pool_settings);
+    // Like libpq, connections over Unix domain sockets don't use TLS
+    let ssl_mode = if postgres_configuration
+        .get_hosts()
+        .iter()
+        .any(|host| matches!(host, Host::Tcp(_)))
+    {
+        ssl_settings.mode()
+    } else {
+        SslMode::Disable
+    };
+    postgres_configuration.ssl_mode(match ssl_mode {
+        SslMode::Disable => tokio_postgres::config::SslMode::Disable,
+        SslMode::Prefer => tokio_postgres::config::SslMode::Prefer,
+        SslMode::Require | SslMode::VerifyCa | SslMode::VerifyFull => {
+            tokio_postgres::config::SslMode::Require
+        }
+    });
+    let deadpool_manager_config = ManagerConfig {
+        recycling_method: recycling_method(pool_settings),
+    };
+    if ssl_mode != SslMode::Disable {
+        let connector = get_tls_connector(ssl_settings)?;
+        let connector = MakeTlsConnector::new(connector);
+        let deadpool_manager =
+            Manager::from_config(postgres_configuration, connector, deadpool_manager_config);
+        build_pool(deadpool_manager, pool_settings)
+    } else {
+        let deadpool_manager =
+            Manager::from_config(postgres_configuration, NoTls, deadpool_manager_config);
+        build_pool(deadpool_manager, pool_settings)
+    }
+}
+
+/// Pool of the replicas of `database_settings`, None when it has none.
+#[tracing::instrument(name = "Generating replicas connection pool.")]
+pub fn generate_replica_pool(database_settings: &DatabaseSettings) -> Result<Option<Pool>, Error> {
+    database_settings
+        .replicas_connection()
+        .map(|replicas_connection| {
+            generate_connection_pool(
+                replicas_connection.build(),
+                &database_settings.ssl,
+                database_settings.pool.as_ref(),
+            )
+        })
+        .transpose()
+}
+
+#[tracing::instrument(name = "Getting postgres client from pool.", skip(pool))]
+pub async fn get_client(pool: Pool) -> Result<Object, PoolError> {
+    pool.get().await
+}
+
+/// Return whether `database_name` exists, with a client connected to the server's default
+/// database for further maintenance queries.
+#[tracing::instrument(name = "Checking if database exists.")]
+pub async fn check_database_exists(
+    database_name: &str,
+    database_settings: &DatabaseSettings,
+) -> Result<(bool, Object), PostgresSetupError> {
+    let postgres_pool_without_database: Pool = generate_connection_pool(
+        database_settings.connection_without_database().build(),
+        &database_settings.ssl,
+        database_settings.pool.as_ref(),
+    )
+    .map_err(PostgresSetupError::Configuration)?;
+    let postgres_client = postgres_pool_without_database
+        .get()
+        .await
+        .map_err(|source| PostgresSetupError::Connect {
+            server: database_settings.connection_without_database().to_string(),
+            source,
+        })?;
+    let exists = postgres_client
+        .query_opt(
+            "SELECT 1 FROM pg_database WHERE datname = $1",
+            &[&database_name],
+        )
+        .await
+        .map_err(|source| PostgresSetupError::Query {
+            action: format!("check existence of database '{}'", database_name),
+            source,
+        })?
+        .is_some();
+    if !exists {
+        info!(
+            "{}::postgres::check_database_exists: Database '{database_name}' was not found",
+            env!("CARGO_PKG_NAME")
+        );
+    }
+    Ok((exists, postgres_client))
+}
+
+/// `CREATE DATABASE` statement with quoted name and options, which can't be bind parameters.
+pub fn create_database_statement(
+    database_name: &str,
+    creation_settings: Option<&DatabaseCreationSettings>,
+) -> String {
+    let mut statement = format!("CREATE DATABASE {}", quote_identifier(database_name));
+    if let Some(creation_settings) = creation_settings {
+        if let Some(ref owner) = creation_settings.owner {
+            statement.push_str(&format!(" OWNER {}", quote_identifier(owner)));
+        }
+        if let Some(ref template) = creation_settings.template {
+            statement.push_str(&format!(" TEMPLATE {}", quote_identifier(template)));
+        }
+        if let Some(ref encoding) = creation_settings.encoding {
+            statement.push_str(&format!(" ENCODING {}", quote_literal(encoding)));
+        }
+        if let Some(ref locale) = creation_settings.locale {
+            statement.push_str(&format!(" LOCALE {}", quote_literal(locale)));
+        }
+    }
+    statement
+}
+
+#[tracing::instrument(name = "Creating database.")]
+pub async fn create_database(
+    database_settings: &mut DatabaseSettings,
+) -> Result<Pool, PostgresSetupError> {
+    let database_name = database_settings
+        .database
+        .to_owned()
+        .ok_or(PostgresSetupError::DatabaseNameMissing)?;
+    let (exists, postgres_client) =
+        check_database_exists(&database_name, database_settings).await?;
+    if !exists {
+        run_simple_query(
+            &postgres_client,
+            &create_database_statement(&database_name, database_settings.creation.as_ref()),
+        )
+        .await
+        .map_err(|source| PostgresSetupError::CreateDatabase {
+            database: database_name.to_owned(),
+            source,
+        })?;
+    }
+    generate_connection_pool(
+        database_settings.connection().build(),
+        &database_settings.ssl,
+        database_settings.pool.as_ref(),
+    )
+    .map_err(PostgresSetupError::Configuration)
+}
+
+#[tracing::instrument(name = "Running simple query.", skip(postgres_client))]
+pub async fn run_simple_query(
+    postgres_client: &Object,
+    query_statement: &str,
+) -> Result<Vec<SimpleQueryMessage>, tokio_postgres::Error> {
+    postgres_client.simple_query(query_statement).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::{DatabaseCreationSettings, PoolSettings};
+    use crate::postgres::{apply_pool_settings, create_database_statement};
+    use std::time::Duration;
+
+    #[test]
+    fn pool_settings_apply_to_sessions() {
+        let mut postgres_configuration = tokio_postgres::Config::new();
+        apply_pool_settings(&mut postgres_configuration, &PoolSettings::default());
+        assert_eq!(None, postgres_configuration.get_application_name());
+        assert_eq!(None, postgres_configuration.get_options());
+        let pool_settings = PoolSettings {
+            applicationname: Some("newsletter-rs".to_owned()),
+            statementtimeoutms: Some(1500),
+            connecttimeoutms: Some(250),
+            keepalives: Some(false),
+            keepalivesidlesecs: Some(30),
+            ..PoolSettings::default()
+        };
+        apply_pool_settings(&mut postgres_configuration, &pool_settings);
+        assert_eq!(
+            Some("newsletter-rs"),
+            postgres_configuration.get_application_name()
+        );
+        assert_eq!(
+            Some("-c statement_timeout=1500"),
+            postgres_configuration.get_options()
+        );
+        assert_eq!(
+            Some(&Duration::from_millis(250)),
+            postgres_configuration.get_connect_timeout()
+        );
+        assert!(!postgres_configuration.get_keepalives());
+        assert_eq!(
+            Duration::from_secs(30),
+            postgres_configuration.get_keepalives_idle()
+        );
+    }
+
+    #[test]
+    fn create_database_statement_quotes_name_and_options() {
+        assert_eq!(
+            "CREATE DATABASE \"news\"\"letter\"",
+            create_database_statement("news\"letter", None)
+        );
+        let creation_settings = DatabaseCreationSettings {
+            owner: Some("app owner".to_owned()),
+            encoding: Some("UTF8".to_owned()),
+            locale: Some("en_US.UTF-8".to_owned()),
+            template: Some("template0".to_owned()),
+        };
+        assert_eq!(
+            "CREATE DATABASE \"newsletter\" OWNER \"app owner\" TEMPLATE \"template0\" ENCODING 'UTF8' LOCALE 'en_US.UTF-8'",
+            create_database_statement("newsletter", Some(&creation_settings))
+        );
+    }
+}
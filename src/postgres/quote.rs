@@ -0,0 +1,36 @@
+/// Quote `identifier` for use as a name in SQL statements, doubling embedded double quotes.
+pub fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Quote `literal` as an SQL string constant, doubling embedded single quotes. Backslashes are
+/// escaped too, as an escape string constant, whatever `standard_conforming_strings` is.
+pub fn quote_literal(literal: &str) -> String {
+    let quoted = literal.replace('\'', "''");
+    if quoted.contains('\\') {
+        format!("E'{}'", quoted.replace('\\', "\\\\"))
+    } else {
+        format!("'{}'", quoted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::postgres::{quote_identifier, quote_literal};
+
+    #[test]
+    fn identifiers_escape_double_quotes() {
+        assert_eq!("\"newsletter\"", quote_identifier("newsletter"));
+        assert_eq!(
+            "\"news\"\"; DROP DATABASE postgres; --\"",
+            quote_identifier("news\"; DROP DATABASE postgres; --")
+        );
+    }
+
+    #[test]
+    fn literals_escape_quotes_and_backslashes() {
+        assert_eq!("'UTF8'", quote_literal("UTF8"));
+        assert_eq!("'it''s'", quote_literal("it's"));
+        assert_eq!("E'a\\\\'' b'", quote_literal("a\\' b"));
+    }
+}
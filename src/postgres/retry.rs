@@ -0,0 +1,164 @@
+use crate::configuration::StartupRetrySettings;
+use crate::postgres::PostgresSetupError;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+/// Delay after `n_failures` failed attempts, doubling from the initial delay up to the maximum.
+/// `jitter_sample` in `[0, 1)` shortens it by up to the configured jitter fraction, so that
+/// replicas started together don't retry in lockstep.
+pub fn startup_retry_delay(
+    settings: &StartupRetrySettings,
+    n_failures: u32,
+    jitter_sample: f64,
+) -> Duration {
+    let exponent = n_failures.saturating_sub(1).min(32);
+    let delay_ms = settings
+        .initialdelayms
+        .saturating_mul(2u64.saturating_pow(exponent))
+        .min(settings.maxdelayms);
+    let jitter = settings.jitter.clamp(0.0, 1.0) * jitter_sample.clamp(0.0, 1.0);
+    Duration::from_millis((delay_ms as f64 * (1.0 - jitter)) as u64)
+}
+
+// Error with its underlying causes, which Display leaves out
+fn error_chain(error: &PostgresSetupError) -> String {
+    anyhow::Chain::new(error)
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
+/// Run `attempt` until it succeeds, fails with a non-transient error or runs out of attempts,
+/// sleeping with backoff in between.
+pub async fn retry_startup<T, F, Fut>(
+    settings: &StartupRetrySettings,
+    operation: &str,
+    mut attempt: F,
+) -> Result<T, PostgresSetupError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, PostgresSetupError>>,
+{
+    let max_attempts = settings.maxattempts.max(1);
+    let mut n_failures = 0;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(error) if error.is_transient() && n_failures + 1 < max_attempts => {
+                n_failures += 1;
+                let delay = startup_retry_delay(settings, n_failures, rand::thread_rng().gen());
+                tracing::warn!(
+                    "{}::postgres::retry_startup: Attempt {}/{} of {} failed, retrying in {}ms: {}",
+                    env!("CARGO_PKG_NAME"),
+                    n_failures,
+                    max_attempts,
+                    operation,
+                    delay.as_millis(),
+                    error_chain(&error)
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(error) => {
+                tracing::error!(
+                    "{}::postgres::retry_startup: Attempt {}/{} of {} failed: {}",
+                    env!("CARGO_PKG_NAME"),
+                    n_failures + 1,
+                    max_attempts,
+                    operation,
+                    error_chain(&error)
+                );
+                return Err(error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::StartupRetrySettings;
+    use crate::postgres::{retry_startup, startup_retry_delay, PostgresSetupError};
+    use deadpool_postgres::PoolError;
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    fn settings(maxattempts: u32) -> StartupRetrySettings {
+        StartupRetrySettings {
+            maxattempts,
+            initialdelayms: 1,
+            maxdelayms: 4,
+            jitter: 0.5,
+        }
+    }
+
+    fn connect_error() -> PostgresSetupError {
+        PostgresSetupError::Connect {
+            server: "localhost:5432".to_owned(),
+            source: PoolError::Closed,
+        }
+    }
+
+    #[test]
+    fn delay_doubles_until_maximum() {
+        let settings = StartupRetrySettings {
+            maxattempts: 10,
+            initialdelayms: 100,
+            maxdelayms: 1000,
+            jitter: 0.5,
+        };
+        let delays: Vec<u128> = (1..=6)
+            .map(|n| startup_retry_delay(&settings, n, 0.0).as_millis())
+            .collect();
+        assert_eq!(vec![100, 200, 400, 800, 1000, 1000], delays);
+        assert_eq!(
+            Duration::from_millis(500),
+            startup_retry_delay(&settings, 4, 0.75)
+        );
+        assert_eq!(
+            Duration::from_millis(1000),
+            startup_retry_delay(&settings, u32::MAX, 0.0)
+        );
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried_until_success() {
+        let attempts = Cell::new(0);
+        let outcome = retry_startup(&settings(5), "test", || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move {
+                if attempt < 3 {
+                    Err(connect_error())
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+        assert_eq!(3, outcome.unwrap());
+    }
+
+    #[tokio::test]
+    async fn retries_stop_after_max_attempts() {
+        let attempts = Cell::new(0);
+        let outcome: Result<(), _> = retry_startup(&settings(4), "test", || {
+            attempts.set(attempts.get() + 1);
+            async { Err(connect_error()) }
+        })
+        .await;
+        assert!(outcome.is_err());
+        assert_eq!(4, attempts.get());
+    }
+
+    #[tokio::test]
+    async fn non_transient_errors_are_not_retried() {
+        let attempts = Cell::new(0);
+        let outcome: Result<(), _> = retry_startup(&settings(4), "test", || {
+            attempts.set(attempts.get() + 1);
+            async { Err(PostgresSetupError::DatabaseNameMissing) }
+        })
+        .await;
+        assert!(outcome.is_err());
+        assert_eq!(1, attempts.get());
+    }
+}
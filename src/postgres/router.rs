@@ -0,0 +1,114 @@
+use deadpool_postgres::{Object, Pool, PoolError};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio_postgres::error::SqlState;
+
+/// Writes sent to a node that was demoted to a read-only standby, e.g. by a failover.
+pub fn is_read_only_error(error: &tokio_postgres::Error) -> bool {
+    error.code() == Some(&SqlState::READ_ONLY_SQL_TRANSACTION)
+}
+
+/// Node a client was taken from, reported by the healthcheck.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostgresTarget {
+    Primary,
+    Replica,
+}
+
+impl fmt::Display for PostgresTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostgresTarget::Primary => write!(f, "primary"),
+            PostgresTarget::Replica => write!(f, "replica"),
+        }
+    }
+}
+
+/// Sends read-only work to the replicas pool while it's healthy and everything else to the
+/// primary. Replicas are marked unhealthy when a client can't be taken from their pool, and
+/// healthy again by the readiness probe. Primary connections are drained when the node they
+/// point at turns out to be read-only.
+pub struct PostgresRouter {
+    primary: Pool,
+    replica: Option<Pool>,
+    replica_healthy: AtomicBool,
+    failover_detected_at: Mutex<Option<SystemTime>>,
+}
+
+impl PostgresRouter {
+    pub fn new(primary: Pool, replica: Option<Pool>) -> Self {
+        Self {
+            primary,
+            replica,
+            replica_healthy: AtomicBool::new(true),
+            failover_detected_at: Mutex::new(None),
+        }
+    }
+
+    pub fn primary(&self) -> &Pool {
+        &self.primary
+    }
+
+    pub fn replica(&self) -> Option<&Pool> {
+        self.replica.as_ref()
+    }
+
+    pub fn is_replica_healthy(&self) -> bool {
+        self.replica.is_some() && self.replica_healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn set_replica_healthy(&self, healthy: bool) {
+        self.replica_healthy.store(healthy, Ordering::Relaxed);
+    }
+
+    /// Last time primary connections were drained after a failover.
+    pub fn failover_detected_at(&self) -> Option<SystemTime> {
+        self.failover_detected_at
+            .lock()
+            .map(|failover_detected_at| *failover_detected_at)
+            .unwrap_or_default()
+    }
+
+    /// Drop `postgres_client` and the idle connections of the primary pool, which point at a
+    /// read-only node, so that new ones are opened against the host accepting writes.
+    ///
+    /// Connections in use by other tasks at that moment aren't dropped: they return to the pool
+    /// still pointing at the demoted node, and `SELECT 1` recycling keeps them since standbys
+    /// answer it. Each is drained in turn once a write on it fails as read-only, or by the
+    /// next readiness probe that takes it.
+    pub fn drain_primary(&self, postgres_client: Option<Object>, reason: &str) {
+        if let Some(postgres_client) = postgres_client {
+            drop(Object::take(postgres_client));
+        }
+        self.primary.retain(|_, _| false);
+        tracing::warn!(
+            "{}::postgres::drain_primary: Drained primary connection pool, {}",
+            env!("CARGO_PKG_NAME"),
+            reason
+        );
+        if let Ok(mut failover_detected_at) = self.failover_detected_at.lock() {
+            *failover_detected_at = Some(SystemTime::now());
+        }
+    }
+
+    /// Client for read-only queries, from the replicas when healthy, otherwise from the primary.
+    pub async fn get_read_client(&self) -> Result<(Object, PostgresTarget), PoolError> {
+        if let Some(replica) = self.replica.as_ref().filter(|_| self.is_replica_healthy()) {
+            match replica.get().await {
+                Ok(postgres_client) => return Ok((postgres_client, PostgresTarget::Replica)),
+                Err(error) => {
+                    tracing::warn!(
+                        "{}::postgres::get_read_client: No healthy replica, reading from the primary: {}",
+                        env!("CARGO_PKG_NAME"),
+                        error
+                    );
+                    self.set_replica_healthy(false);
+                }
+            }
+        }
+        let postgres_client = self.primary.get().await?;
+        Ok((postgres_client, PostgresTarget::Primary))
+    }
+}
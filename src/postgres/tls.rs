@@ -0,0 +1,197 @@
+use crate::configuration::{SslMode, SslSettings};
+use anyhow::{anyhow, Context, Error, Result};
+use native_tls::{Certificate, Identity, TlsConnector};
+use std::fs;
+
+static PEM_CERTIFICATE_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+static PEM_CERTIFICATE_END: &str = "-----END CERTIFICATE-----";
+
+fn read_pem_file(path: &str) -> Result<String, Error> {
+    fs::read_to_string(path).with_context(|| {
+        format!(
+            "{}::postgres::get_tls_connector: Failed to read PEM file '{}'",
+            env!("CARGO_PKG_NAME"),
+            path
+        )
+    })
+}
+
+/// Parse every certificate of a PEM bundle, failing on the first malformed one rather than
+/// connecting with a partial set of trusted authorities. Text around the blocks, like the
+/// `# Issuer:` comments of curl's bundle, is ignored as RFC 7468 allows. `origin` names the
+/// bundle in errors.
+pub fn parse_pem_certificates(pem: &str, origin: &str) -> Result<Vec<Certificate>, Error> {
+    let mut certificates = Vec::new();
+    let mut remaining = pem;
+    while let Some(begin) = remaining.find(PEM_CERTIFICATE_BEGIN) {
+        let index = certificates.len() + 1;
+        let block = &remaining[begin..];
+        // A block missing its END line would otherwise swallow the next certificate
+        let end = block
+            .find(PEM_CERTIFICATE_END)
+            .map(|end| end + PEM_CERTIFICATE_END.len())
+            .filter(|end| !block[1..*end].contains(PEM_CERTIFICATE_BEGIN))
+            .ok_or_else(|| {
+                anyhow!(
+                    "{}::postgres::parse_pem_certificates: Certificate {} of {} has no END line",
+                    env!("CARGO_PKG_NAME"),
+                    index,
+                    origin
+                )
+            })?;
+        let certificate = Certificate::from_pem(&block.as_bytes()[..end]).with_context(|| {
+            format!(
+                "{}::postgres::parse_pem_certificates: Failed to parse certificate {} of {}",
+                env!("CARGO_PKG_NAME"),
+                index,
+                origin
+            )
+        })?;
+        certificates.push(certificate);
+        remaining = &block[end..];
+    }
+    if certificates.is_empty() {
+        return Err(anyhow!(
+            "{}::postgres::parse_pem_certificates: No certificate in {}",
+            env!("CARGO_PKG_NAME"),
+            origin
+        ));
+    }
+    Ok(certificates)
+}
+
+/// TLS connector honouring the verification level of the ssl mode, the additional trusted
+/// authorities and the client certificate of `ssl_settings`.
+pub fn get_tls_connector(ssl_settings: &SslSettings) -> Result<TlsConnector, Error> {
+    let mut connector = TlsConnector::builder();
+    match ssl_settings.mode() {
+        // Like libpq, encrypt without checking who is on the other side
+        SslMode::Disable | SslMode::Prefer | SslMode::Require => {
+            connector.danger_accept_invalid_certs(true);
+        }
+        SslMode::VerifyCa => {
+            connector.danger_accept_invalid_hostnames(true);
+        }
+        SslMode::VerifyFull => {}
+    }
+    if let Some(ref cacertificates) = ssl_settings.cacertificates {
+        for certificate in parse_pem_certificates(cacertificates, "database.ssl.cacertificates")? {
+            connector.add_root_certificate(certificate);
+        }
+    }
+    if let Some(ref cacertificatesfile) = ssl_settings.cacertificatesfile {
+        let bundle = read_pem_file(cacertificatesfile)?;
+        for certificate in parse_pem_certificates(&bundle, &format!("'{}'", cacertificatesfile))? {
+            connector.add_root_certificate(certificate);
+        }
+    }
+    match (
+        ssl_settings.clientcertificatefile.as_ref(),
+        ssl_settings.clientkeyfile.as_ref(),
+    ) {
+        (Some(certificate_file), Some(key_file)) => {
+            let certificate = read_pem_file(certificate_file)?;
+            let key = read_pem_file(key_file)?;
+            let identity = Identity::from_pkcs8(certificate.as_bytes(), key.as_bytes())
+                .with_context(|| {
+                    format!(
+                        "{}::postgres::get_tls_connector: Failed to load client certificate '{}' with PKCS#8 key '{}'",
+                        env!("CARGO_PKG_NAME"),
+                        certificate_file,
+                        key_file
+                    )
+                })?;
+            connector.identity(identity);
+        }
+        (None, None) => {}
+        _ => {
+            return Err(anyhow!(
+                "{}::postgres::get_tls_connector: database.ssl.clientcertificatefile and database.ssl.clientkeyfile must be set together",
+                env!("CARGO_PKG_NAME")
+            ))
+        }
+    }
+    connector.build().with_context(|| {
+        format!(
+            "{}::postgres::get_tls_connector: Failed to build TLS connector",
+            env!("CARGO_PKG_NAME")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::{SslMode, SslSettings};
+    use crate::postgres::{get_tls_connector, parse_pem_certificates};
+
+    static TEST_CA: &str =
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/tls/ca.pem"));
+
+    fn testdata(filename: &str) -> Option<String> {
+        Some(format!(
+            "{}/testdata/tls/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            filename
+        ))
+    }
+
+    #[test]
+    fn bundles_are_parsed_whole() {
+        let bundle = format!("{}\n{}\n\n", TEST_CA, TEST_CA);
+        assert_eq!(2, parse_pem_certificates(&bundle, "test").unwrap().len());
+    }
+
+    #[test]
+    fn text_around_certificates_is_ignored() {
+        let bundle = format!(
+            "##\n## Bundle of CA Root Certificates\n##\n\n# Issuer: CN=Test CA\n# Subject: CN=Test CA\n{}\nTest CA 2\n=========\n{}",
+            TEST_CA, TEST_CA
+        );
+        assert_eq!(2, parse_pem_certificates(&bundle, "test").unwrap().len());
+        assert!(parse_pem_certificates("# Issuer: CN=Test CA\n", "test").is_err());
+    }
+
+    #[test]
+    fn malformed_certificates_are_rejected() {
+        let truncated = TEST_CA.replacen("-----END CERTIFICATE-----", "", 1);
+        assert!(parse_pem_certificates(&truncated, "test").is_err());
+        let bundle = format!("{}{}", truncated, TEST_CA);
+        assert!(parse_pem_certificates(&bundle, "test").is_err());
+        let garbled = "-----BEGIN CERTIFICATE-----\nM***EXAMPLE***\n-----END CERTIFICATE-----\n";
+        assert!(parse_pem_certificates(&format!("{}{}", TEST_CA, garbled), "test").is_err());
+        assert!(parse_pem_certificates("\n", "test").is_err());
+    }
+
+    #[test]
+    fn tls_connector_loads_files_and_client_identity() {
+        let ssl_settings = SslSettings {
+            mode: Some(SslMode::VerifyCa),
+            cacertificatesfile: testdata("ca.pem"),
+            clientcertificatefile: testdata("client.pem"),
+            clientkeyfile: testdata("client.key"),
+            ..SslSettings::default()
+        };
+        assert!(get_tls_connector(&ssl_settings).is_ok());
+        let ssl_settings = SslSettings {
+            clientkeyfile: None,
+            ..ssl_settings
+        };
+        assert!(get_tls_connector(&ssl_settings).is_err());
+        let ssl_settings = SslSettings {
+            mode: Some(SslMode::VerifyFull),
+            cacertificatesfile: testdata("missing.pem"),
+            ..SslSettings::default()
+        };
+        assert!(get_tls_connector(&ssl_settings).is_err());
+    }
+
+    #[test]
+    fn legacy_tls_flag_maps_to_ssl_modes() {
+        let mut ssl_settings = SslSettings::default();
+        assert_eq!(SslMode::Disable, ssl_settings.mode());
+        ssl_settings.tls = true;
+        assert_eq!(SslMode::VerifyFull, ssl_settings.mode());
+        ssl_settings.mode = Some(SslMode::Require);
+        assert_eq!(SslMode::Require, ssl_settings.mode());
+    }
+}
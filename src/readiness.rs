@@ -1,5 +1,6 @@
+use crate::postgres::{is_read_only_error, PostgresRouter, PostgresTarget};
 use anyhow::Result;
-use deadpool_postgres::Pool;
+use deadpool_postgres::Object;
 use std::sync::Arc;
 use std::time::SystemTime;
 use time::{error, format_description::well_known::Rfc3339, OffsetDateTime};
@@ -29,6 +30,8 @@ pub struct PostgresReadCheck {
     pub time: Option<String>,
     pub output: String,
     pub version: Option<String>,
+    // Node that served the read, primary or replica
+    pub target: Option<String>,
 }
 
 pub static STATUS_PASS: &str = "pass";
@@ -43,30 +46,18 @@ pub struct PostgresWriteCheck {
     pub pg_is_in_recovery: Option<bool>,
     pub output: String,
     pub version: Option<String>,
+    // Last time primary connections were drained after a failover
+    pub failover_detected_at: Option<String>,
 }
 
-pub async fn probe_readiness(postgres_pool: Arc<Pool>) -> HealthResponse {
-    let now_systemtime = SystemTime::now();
-    let now_string = to_rfc3339(now_systemtime).unwrap();
-    let postgres_client_error = "DB client error";
-    let optional_postgres_client = match postgres_pool.get().await {
-        Ok(manager) => Some(manager),
-        Err(error) => {
-            tracing::error!("Could not retrieve postgres client from pool, {}.", error);
-            None
-        }
-    };
-    if optional_postgres_client.is_none() {
-        return build_postgres_readwrite_response(
-            STATUS_FAIL,
-            STATUS_FAIL,
-            STATUS_WARN,
-            &now_string,
-            postgres_client_error,
-        );
-    }
-    let postgres_client = optional_postgres_client.unwrap();
-    let statement_read_error = "DB read statement error.";
+struct PostgresStatus {
+    datetime: OffsetDateTime,
+    recovery: bool,
+    version: String,
+}
+
+// Output of the failed step when the status query fails
+async fn query_postgres_status(postgres_client: &Object) -> Result<PostgresStatus, &'static str> {
     let statement_read = match postgres_client
         .prepare_cached(
             r#"
@@ -75,50 +66,142 @@ pub async fn probe_readiness(postgres_pool: Arc<Pool>) -> HealthResponse {
         )
         .await
     {
-        Ok(statement) => Some(statement),
+        Ok(statement) => statement,
         Err(error) => {
             tracing::error!("Failed to prepare cached healthcheck read query: {}", error);
-            None
+            return Err("DB read statement error.");
         }
     };
-    if statement_read.is_none() {
-        return build_postgres_readwrite_response(
-            STATUS_FAIL,
-            STATUS_FAIL,
-            STATUS_WARN,
-            &now_string,
-            statement_read_error,
-        );
-    }
-    let read_error = "DB read error.";
-    let optional_row = match postgres_client.query(&statement_read.unwrap(), &[]).await {
-        Ok(row) => Some(row),
+    let row_results = match postgres_client.query(&statement_read, &[]).await {
+        Ok(row) => row,
         Err(error) => {
             tracing::warn!("Failed healthcheck query: {}", error);
+            return Err("DB read error.");
+        }
+    };
+    Ok(PostgresStatus {
+        datetime: row_results[0].get("datetime"),
+        recovery: row_results[0].get("recovery"),
+        version: row_results[0].get::<_, &str>("pg_version").to_owned(),
+    })
+}
+
+// Reads from the replicas when configured, updating their health for the router
+async fn probe_replica(postgres_router: &PostgresRouter) -> Option<PostgresStatus> {
+    let replica_pool = postgres_router.replica()?;
+    let replica_status = match replica_pool.get().await {
+        Ok(replica_client) => query_postgres_status(&replica_client).await.ok(),
+        Err(error) => {
+            tracing::warn!(
+                "Could not retrieve postgres client from replicas pool, {}.",
+                error
+            );
             None
         }
     };
-    if optional_row.is_none() {
-        return build_postgres_readwrite_response(
-            STATUS_FAIL,
-            STATUS_FAIL,
+    postgres_router.set_replica_healthy(replica_status.is_some());
+    replica_status
+}
+
+fn postgres_read_check(
+    postgres_status: &PostgresStatus,
+    target: PostgresTarget,
+    output: &str,
+) -> PostgresReadCheck {
+    build_postgres_read_response(
+        STATUS_PASS,
+        Some(to_rfc3339(postgres_status.datetime).unwrap()),
+        Some(postgres_status.version.to_owned()),
+        Some(target),
+        output,
+    )
+}
+
+pub async fn probe_readiness(postgres_router: Arc<PostgresRouter>) -> HealthResponse {
+    let mut health_response = probe_postgres(&postgres_router).await;
+    health_response.checks.postgres_write.failover_detected_at = postgres_router
+        .failover_detected_at()
+        .and_then(|failover_detected_at| to_rfc3339(failover_detected_at).ok());
+    health_response
+}
+
+async fn probe_postgres(postgres_router: &PostgresRouter) -> HealthResponse {
+    let now_systemtime = SystemTime::now();
+    let now_string = to_rfc3339(now_systemtime).unwrap();
+    let output_pass = "";
+    let replica_status = probe_replica(postgres_router).await;
+    // Reads fall back to the primary, which still serves them
+    let replica_fallback = postgres_router.replica().is_some() && replica_status.is_none();
+    let read_output = if replica_fallback {
+        "No healthy replica, reading from the primary."
+    } else {
+        output_pass
+    };
+    let postgres_client_error = "DB client error";
+    let optional_postgres_client = match postgres_router.primary().get().await {
+        Ok(manager) => Some(manager),
+        Err(error) => {
+            tracing::error!("Could not retrieve postgres client from pool, {}.", error);
+            None
+        }
+    };
+    let primary_status = match optional_postgres_client {
+        Some(ref postgres_client) => query_postgres_status(postgres_client).await,
+        None => Err(postgres_client_error),
+    };
+    let primary_status = match primary_status {
+        Ok(primary_status) => primary_status,
+        Err(primary_error) => {
+            // Replicas keep serving reads while the primary is unavailable
+            if let Some(ref replica_status) = replica_status {
+                return get_healthcheck_object(
+                    STATUS_WARN,
+                    &now_string,
+                    output_pass,
+                    postgres_read_check(replica_status, PostgresTarget::Replica, output_pass),
+                    build_postgres_write_response(STATUS_FAIL, None, None, None, primary_error),
+                );
+            }
+            return build_postgres_readwrite_response(
+                STATUS_FAIL,
+                STATUS_FAIL,
+                STATUS_WARN,
+                &now_string,
+                primary_error,
+            );
+        }
+    };
+    let postgres_client = optional_postgres_client.unwrap();
+    let postgres_recovery = primary_status.recovery;
+    let postgres_version = primary_status.version.as_str();
+    let postgres_read = match replica_status {
+        Some(ref replica_status) => {
+            postgres_read_check(replica_status, PostgresTarget::Replica, read_output)
+        }
+        None => postgres_read_check(&primary_status, PostgresTarget::Primary, read_output),
+    };
+    let global_status_pass = if replica_fallback {
+        STATUS_WARN
+    } else {
+        STATUS_PASS
+    };
+    // Connected primary was demoted by a failover, new connections find the read-write host
+    if postgres_recovery {
+        postgres_router.drain_primary(Some(postgres_client), "primary is in recovery");
+        return get_healthcheck_object(
             STATUS_WARN,
             &now_string,
-            read_error,
+            output_pass,
+            postgres_read,
+            build_postgres_write_response(
+                STATUS_FAIL,
+                None,
+                Some(postgres_recovery),
+                Some(postgres_version.to_owned()),
+                "Primary is in recovery, connection pool drained.",
+            ),
         );
     }
-    let row_results = optional_row.unwrap();
-    let postgres_read_timestamp: OffsetDateTime = row_results[0].get("datetime");
-    let postgres_read_timestamp_string = to_rfc3339(postgres_read_timestamp).unwrap();
-    let postgres_recovery: bool = row_results[0].get("recovery");
-    let postgres_version: &str = row_results[0].get("pg_version");
-    let output_pass = "";
-    let postgres_read = build_postgres_read_response(
-        STATUS_PASS,
-        Some(postgres_read_timestamp_string.to_owned()),
-        Some(postgres_version.to_owned()),
-        output_pass,
-    );
     let statement_write_error = "DB write statement error.";
     let statement_write = match postgres_client
         .prepare_cached(
@@ -161,6 +244,7 @@ pub async fn probe_readiness(postgres_pool: Arc<Pool>) -> HealthResponse {
     }
     let updated_by_parameter = format!("newsletter-rs {}", &now_string);
     let write_error = "DB write error.";
+    let mut read_only_node = false;
     let optional_row = match postgres_client
         .query(&statement_write.unwrap(), &[&updated_by_parameter])
         .await
@@ -168,9 +252,16 @@ pub async fn probe_readiness(postgres_pool: Arc<Pool>) -> HealthResponse {
         Ok(row) => Some(row),
         Err(error) => {
             tracing::warn!("Failed healthcheck query: {}", error);
+            read_only_node = is_read_only_error(&error);
             None
         }
     };
+    if read_only_node {
+        postgres_router.drain_primary(
+            Some(postgres_client),
+            "healthcheck write failed on a read-only node",
+        );
+    }
     if optional_row.is_none() {
         postgres_write = build_postgres_write_response(
             STATUS_FAIL,
@@ -200,7 +291,7 @@ pub async fn probe_readiness(postgres_pool: Arc<Pool>) -> HealthResponse {
     );
 
     get_healthcheck_object(
-        STATUS_PASS,
+        global_status_pass,
         &now_string,
         output_pass,
         postgres_read,
@@ -219,12 +310,14 @@ pub fn build_postgres_read_response(
     status: &str,
     time: Option<String>,
     pg_version: Option<String>,
+    target: Option<PostgresTarget>,
     output: &str,
 ) -> PostgresReadCheck {
     PostgresReadCheck {
         status: status.to_owned(),
         time,
         version: pg_version,
+        target: target.map(|target| target.to_string()),
         output: output.to_owned(),
     }
 }
@@ -242,6 +335,7 @@ pub fn build_postgres_write_response(
         pg_is_in_recovery,
         version: pg_version,
         output: output.to_owned(),
+        failover_detected_at: None,
     }
 }
 
@@ -273,7 +367,8 @@ pub fn build_postgres_readwrite_response(
     now_string: &str,
     output: &str,
 ) -> HealthResponse {
-    let postgres_read = build_postgres_read_response(postgres_read_status, None, None, output);
+    let postgres_read =
+        build_postgres_read_response(postgres_read_status, None, None, None, output);
     let postgres_write =
         build_postgres_write_response(postgres_write_status, None, None, None, output);
     get_healthcheck_object(global_status, now_string, "", postgres_read, postgres_write)
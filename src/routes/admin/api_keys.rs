@@ -0,0 +1,180 @@
+use crate::authentication::{create_api_key, require_admin_session, revoke_api_key, ApiKeyScope};
+use crate::readiness::to_rfc3339;
+use crate::routes::get_postgres_client;
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
+use anyhow::{Error, Result};
+use deadpool_postgres::{Object, Pool};
+use secrecy::ExposeSecret;
+use std::sync::Arc;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct ApiKeyData {
+    pub name: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct CreatedApiKey {
+    pub key_id: Uuid,
+    pub name: String,
+    pub key_prefix: String,
+    pub scopes: Vec<String>,
+    // Only returned at creation, it can't be recovered afterwards
+    pub api_key: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct ApiKeySummary {
+    pub key_id: Uuid,
+    pub name: String,
+    pub key_prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+fn parse_api_key_data(data: &ApiKeyData) -> Result<Vec<ApiKeyScope>, String> {
+    if data.name.trim().is_empty() {
+        return Err(
+            "Provided API key name appears to be blank or empty which is invalid.".to_owned(),
+        );
+    }
+    if data.scopes.is_empty() {
+        return Err("At least one API key scope must be provided.".to_owned());
+    }
+    let mut scopes = Vec::with_capacity(data.scopes.len());
+    for scope in &data.scopes {
+        let scope: ApiKeyScope = scope.parse()?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    Ok(scopes)
+}
+
+#[tracing::instrument(
+    name = "Creating API key for machine client.",
+    skip(request, data),
+    fields(key_name = %data.name)
+)]
+pub async fn create_admin_api_key(
+    request: HttpRequest,
+    data: web::Json<ApiKeyData>,
+) -> impl Responder {
+    let admin = match require_admin_session(&request) {
+        Ok(admin) => admin,
+        Err(response) => return response,
+    };
+    let scopes = match parse_api_key_data(&data) {
+        Ok(scopes) => scopes,
+        Err(error) => {
+            tracing::error!("routes/admin/api_keys.rs {}", error);
+            return HttpResponse::BadRequest().body(error);
+        }
+    };
+    let postgres_client = match admin_postgres_client(&request).await {
+        Ok(postgres_client) => postgres_client,
+        Err(response) => return response,
+    };
+    match create_api_key(&postgres_client, data.name.trim(), &scopes, admin.user_id).await {
+        Ok(generated) => HttpResponse::Created()
+            .insert_header((
+                header::LOCATION,
+                format!("/admin/api-keys/{}", generated.key_id),
+            ))
+            .json(CreatedApiKey {
+                key_id: generated.key_id,
+                name: data.name.trim().to_owned(),
+                key_prefix: generated.key_prefix,
+                scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+                api_key: generated.key.expose_secret().to_owned(),
+            }),
+        Err(error) => {
+            tracing::error!("Failed to create API key: {}", error);
+            HttpResponse::InternalServerError().body("DB error while creating API key.")
+        }
+    }
+}
+
+#[tracing::instrument(name = "Listing API keys.", skip(request))]
+pub async fn list_admin_api_keys(request: HttpRequest) -> impl Responder {
+    if let Err(response) = require_admin_session(&request) {
+        return response;
+    }
+    let postgres_client = match admin_postgres_client(&request).await {
+        Ok(postgres_client) => postgres_client,
+        Err(response) => return response,
+    };
+    match get_api_key_summaries(&postgres_client).await {
+        Ok(api_keys) => HttpResponse::Ok().json(api_keys),
+        Err(error) => {
+            tracing::error!("Failed to list API keys: {}", error);
+            HttpResponse::InternalServerError().body("DB error while listing API keys.")
+        }
+    }
+}
+
+#[tracing::instrument(name = "Revoking API key of machine client.", skip(request))]
+pub async fn revoke_admin_api_key(request: HttpRequest, path: web::Path<Uuid>) -> impl Responder {
+    if let Err(response) = require_admin_session(&request) {
+        return response;
+    }
+    let key_id = path.into_inner();
+    let postgres_client = match admin_postgres_client(&request).await {
+        Ok(postgres_client) => postgres_client,
+        Err(response) => return response,
+    };
+    match revoke_api_key(&postgres_client, key_id).await {
+        Ok(0) => HttpResponse::NotFound().body(format!("Unknown or revoked API key '{key_id}'.")),
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(error) => {
+            tracing::error!("Failed to revoke API key: {}", error);
+            HttpResponse::InternalServerError().body("DB error while revoking API key.")
+        }
+    }
+}
+
+async fn admin_postgres_client(request: &HttpRequest) -> Result<Object, HttpResponse> {
+    let postgres_pool = match request.app_data::<Arc<Pool>>() {
+        Some(postgres_pool) => postgres_pool,
+        None => {
+            tracing::error!("Could not retrieve postgres pool from app_data.");
+            return Err(HttpResponse::InternalServerError().body("DB pool error."));
+        }
+    };
+    get_postgres_client(postgres_pool)
+        .await
+        .ok_or_else(|| HttpResponse::InternalServerError().body("DB client error."))
+}
+
+#[tracing::instrument(name = "Querying API keys.", skip(postgres_client))]
+pub async fn get_api_key_summaries(postgres_client: &Object) -> Result<Vec<ApiKeySummary>, Error> {
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                SELECT key_id, name, key_prefix, scopes, created_at, last_used_at, revoked_at
+                FROM newsletter.api_keys ORDER BY created_at
+            "#,
+        )
+        .await?;
+    let rows = postgres_client.query(&statement, &[]).await?;
+    let mut api_keys = Vec::with_capacity(rows.len());
+    for row in rows {
+        let created_at: OffsetDateTime = row.get("created_at");
+        let last_used_at: Option<OffsetDateTime> = row.get("last_used_at");
+        let revoked_at: Option<OffsetDateTime> = row.get("revoked_at");
+        api_keys.push(ApiKeySummary {
+            key_id: row.get("key_id"),
+            name: row.get("name"),
+            key_prefix: row.get("key_prefix"),
+            scopes: row.get("scopes"),
+            created_at: to_rfc3339(created_at)?,
+            last_used_at: last_used_at.map(to_rfc3339).transpose()?,
+            revoked_at: revoked_at.map(to_rfc3339).transpose()?,
+        });
+    }
+    Ok(api_keys)
+}
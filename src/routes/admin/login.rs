@@ -0,0 +1,124 @@
+use crate::authentication::{
+    create_session, delete_session, require_admin_session, sign_session_cookie,
+    validate_credentials, SESSION_COOKIE_NAME,
+};
+use crate::routes::get_postgres_client;
+use crate::startup::{AdminSessionTtl, HmacSecret};
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use deadpool_postgres::Pool;
+use secrecy::SecretString;
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+#[derive(serde::Deserialize)]
+pub struct LoginData {
+    pub username: String,
+    pub password: SecretString,
+}
+
+fn session_cookie(value: String, max_age: CookieDuration) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE_NAME, value)
+        .path("/admin")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .max_age(max_age)
+        .finish()
+}
+
+#[tracing::instrument(
+    name = "Logging in admin.",
+    skip(request, credentials),
+    fields(username = %credentials.username)
+)]
+pub async fn admin_login(
+    request: HttpRequest,
+    credentials: web::Json<LoginData>,
+) -> impl Responder {
+    let credentials = credentials.into_inner();
+    let postgres_pool = match request.app_data::<Arc<Pool>>() {
+        Some(postgres_pool) => postgres_pool,
+        None => {
+            tracing::error!("Could not retrieve postgres pool from app_data.");
+            return HttpResponse::InternalServerError().body("DB pool error while logging in.");
+        }
+    };
+    let (hmac_secret, session_ttl) = match (
+        request.app_data::<Arc<HmacSecret>>(),
+        request.app_data::<Arc<AdminSessionTtl>>(),
+    ) {
+        (Some(hmac_secret), Some(session_ttl)) => (hmac_secret, session_ttl),
+        _ => {
+            tracing::error!("Could not retrieve session settings from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("Configuration error while logging in.");
+        }
+    };
+    let postgres_client = match get_postgres_client(postgres_pool).await {
+        Some(postgres_client) => postgres_client,
+        None => {
+            return HttpResponse::InternalServerError().body("DB client error while logging in.")
+        }
+    };
+    let user_id = match validate_credentials(
+        &postgres_client,
+        &credentials.username,
+        credentials.password,
+    )
+    .await
+    {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            tracing::warn!("Rejected admin login with invalid credentials");
+            return HttpResponse::Unauthorized().body("Invalid username or password.");
+        }
+        Err(error) => {
+            tracing::error!("Failed to validate admin credentials: {:?}", error);
+            return HttpResponse::InternalServerError().body("Error while logging in.");
+        }
+    };
+    let session_id = match create_session(&postgres_client, user_id, session_ttl.0).await {
+        Ok(session_id) => session_id,
+        Err(error) => {
+            tracing::error!("Failed to create admin session: {}", error);
+            return HttpResponse::InternalServerError().body("DB error while logging in.");
+        }
+    };
+    let max_age = CookieDuration::try_from(session_ttl.0).unwrap_or(CookieDuration::MAX);
+    HttpResponse::Ok()
+        .cookie(session_cookie(
+            sign_session_cookie(&session_id, &hmac_secret.0),
+            max_age,
+        ))
+        .finish()
+}
+
+#[tracing::instrument(name = "Logging out admin.", skip(request))]
+pub async fn admin_logout(request: HttpRequest) -> impl Responder {
+    let admin = match require_admin_session(&request) {
+        Ok(admin) => admin,
+        Err(response) => return response,
+    };
+    let postgres_pool = match request.app_data::<Arc<Pool>>() {
+        Some(postgres_pool) => postgres_pool,
+        None => {
+            tracing::error!("Could not retrieve postgres pool from app_data.");
+            return HttpResponse::InternalServerError().body("DB pool error while logging out.");
+        }
+    };
+    let postgres_client = match get_postgres_client(postgres_pool).await {
+        Some(postgres_client) => postgres_client,
+        None => {
+            return HttpResponse::InternalServerError().body("DB client error while logging out.")
+        }
+    };
+    if let Err(error) = delete_session(&postgres_client, &admin.session_id).await {
+        tracing::error!("Failed to delete admin session: {}", error);
+        return HttpResponse::InternalServerError().body("DB error while logging out.");
+    }
+    // Expire the cookie on the client as well
+    HttpResponse::Ok()
+        .cookie(session_cookie(String::new(), CookieDuration::ZERO))
+        .finish()
+}
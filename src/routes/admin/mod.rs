@@ -0,0 +1,13 @@
+mod api_keys;
+mod login;
+mod newsletters;
+mod subscribers;
+mod subscribers_export;
+mod subscribers_import;
+
+pub use api_keys::*;
+pub use login::*;
+pub use newsletters::*;
+pub use subscribers::*;
+pub use subscribers_export::*;
+pub use subscribers_import::*;
@@ -0,0 +1,236 @@
+use crate::authentication::{require_scope, ApiKeyScope};
+use crate::readiness::to_rfc3339;
+use crate::routes::get_postgres_client;
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Responder};
+use anyhow::{Error, Result};
+use deadpool_postgres::{Object, Pool, Transaction};
+use std::sync::Arc;
+use time::OffsetDateTime;
+use uuid::{NoContext, Timestamp, Uuid};
+
+#[derive(serde::Deserialize)]
+pub struct NewsletterIssueData {
+    pub title: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct PublishedNewsletterIssue {
+    pub issue_id: Uuid,
+}
+
+#[derive(serde::Serialize)]
+pub struct NewsletterIssueProgress {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub total_recipients: i32,
+    pub delivered: i32,
+    pub failed: i32,
+    pub published_at: String,
+    pub completed_at: Option<String>,
+}
+
+fn validate_newsletter_issue(issue: &NewsletterIssueData) -> Result<(), String> {
+    if issue.title.trim().is_empty() {
+        return Err(
+            "Provided newsletter title appears to be blank or empty which is invalid.".to_owned(),
+        );
+    }
+    if issue.html_content.trim().is_empty() || issue.text_content.trim().is_empty() {
+        return Err(
+            "Both html_content and text_content of the newsletter must be provided.".to_owned(),
+        );
+    }
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Publishing newsletter issue.",
+    skip(request, issue),
+    fields(issue_title = %issue.title)
+)]
+pub async fn publish_newsletter(
+    request: HttpRequest,
+    issue: web::Json<NewsletterIssueData>,
+) -> impl Responder {
+    if let Err(response) = require_scope(&request, ApiKeyScope::NewslettersSend) {
+        return response;
+    }
+    if let Err(error) = validate_newsletter_issue(&issue) {
+        tracing::error!("routes/admin/newsletters.rs {}", error);
+        return HttpResponse::BadRequest().body(error);
+    }
+    let postgres_pool = match request.app_data::<Arc<Pool>>() {
+        Some(postgres_pool) => postgres_pool,
+        None => {
+            tracing::error!("Could not retrieve postgres pool from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("DB pool error while publishing newsletter.");
+        }
+    };
+    let mut postgres_client = match get_postgres_client(postgres_pool).await {
+        Some(postgres_client) => postgres_client,
+        None => {
+            return HttpResponse::InternalServerError()
+                .body("DB client error while publishing newsletter.")
+        }
+    };
+    let transaction = match postgres_client.transaction().await {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            tracing::error!("Failed to begin newsletter transaction: {}", error);
+            return HttpResponse::InternalServerError()
+                .body("DB transaction error while publishing newsletter.");
+        }
+    };
+    // The issue and its delivery tasks are saved atomically, the delivery worker picks them up
+    let issue_id = match insert_newsletter_issue(&transaction, &issue).await {
+        Ok(issue_id) => issue_id,
+        Err(error) => {
+            tracing::error!("Failed to insert newsletter issue: {}", error);
+            return HttpResponse::InternalServerError()
+                .body("DB error while publishing newsletter.");
+        }
+    };
+    if let Err(error) = enqueue_delivery_tasks(&transaction, issue_id).await {
+        tracing::error!("Failed to enqueue newsletter delivery tasks: {}", error);
+        return HttpResponse::InternalServerError().body("DB error while publishing newsletter.");
+    }
+    if let Err(error) = transaction.commit().await {
+        tracing::error!("Failed to commit newsletter transaction: {}", error);
+        return HttpResponse::InternalServerError()
+            .body("DB transaction error while publishing newsletter.");
+    }
+    HttpResponse::Accepted()
+        .insert_header((header::LOCATION, format!("/admin/newsletters/{}", issue_id)))
+        .json(PublishedNewsletterIssue { issue_id })
+}
+
+#[tracing::instrument(name = "Retrieving newsletter issue progress.", skip(request))]
+pub async fn newsletter_progress(request: HttpRequest, path: web::Path<Uuid>) -> impl Responder {
+    if let Err(response) = require_scope(&request, ApiKeyScope::NewslettersRead) {
+        return response;
+    }
+    let issue_id = path.into_inner();
+    let postgres_pool = match request.app_data::<Arc<Pool>>() {
+        Some(postgres_pool) => postgres_pool,
+        None => {
+            tracing::error!("Could not retrieve postgres pool from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("DB pool error while retrieving newsletter progress.");
+        }
+    };
+    let postgres_client = match get_postgres_client(postgres_pool).await {
+        Some(postgres_client) => postgres_client,
+        None => {
+            return HttpResponse::InternalServerError()
+                .body("DB client error while retrieving newsletter progress.")
+        }
+    };
+    match get_newsletter_issue_progress(&postgres_client, issue_id).await {
+        Ok(Some(progress)) => HttpResponse::Ok().json(progress),
+        Ok(None) => {
+            HttpResponse::NotFound().body(format!("Unknown newsletter issue '{issue_id}'."))
+        }
+        Err(error) => {
+            tracing::error!("Failed to retrieve newsletter issue progress: {}", error);
+            HttpResponse::InternalServerError()
+                .body("DB error while retrieving newsletter progress.")
+        }
+    }
+}
+
+#[tracing::instrument(name = "Saving newsletter issue.", skip(transaction, issue))]
+pub async fn insert_newsletter_issue(
+    transaction: &Transaction<'_>,
+    issue: &NewsletterIssueData,
+) -> Result<Uuid, tokio_postgres::Error> {
+    let issue_id: Uuid = Uuid::new_v7(Timestamp::now(NoContext));
+    let statement = transaction
+        .prepare_cached(
+            r#"
+                INSERT INTO newsletter.newsletter_issues
+                    (id, title, html_content, text_content)
+                VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .await?;
+    transaction
+        .execute(
+            &statement,
+            &[
+                &issue_id,
+                &issue.title,
+                &issue.html_content,
+                &issue.text_content,
+            ],
+        )
+        .await?;
+    Ok(issue_id)
+}
+
+#[tracing::instrument(name = "Enqueueing newsletter delivery tasks.", skip(transaction))]
+pub async fn enqueue_delivery_tasks(
+    transaction: &Transaction<'_>,
+    issue_id: Uuid,
+) -> Result<u64, tokio_postgres::Error> {
+    let enqueue_statement = transaction
+        .prepare_cached(
+            r#"
+                INSERT INTO newsletter.issue_delivery_queue (issue_id, subscriber_id)
+                SELECT $1, id FROM newsletter.subscription WHERE status = 'confirmed'
+            "#,
+        )
+        .await?;
+    let total_recipients = transaction
+        .execute(&enqueue_statement, &[&issue_id])
+        .await?;
+    let total_statement = transaction
+        .prepare_cached(
+            r#"
+                UPDATE newsletter.newsletter_issues
+                SET total_recipients = $2,
+                    status = CASE WHEN $2 = 0 THEN 'completed' ELSE status END,
+                    completed_at = CASE WHEN $2 = 0 THEN now() ELSE completed_at END
+                WHERE id = $1
+            "#,
+        )
+        .await?;
+    transaction
+        .execute(&total_statement, &[&issue_id, &(total_recipients as i32)])
+        .await?;
+    Ok(total_recipients)
+}
+
+#[tracing::instrument(name = "Querying newsletter issue progress.", skip(postgres_client))]
+pub async fn get_newsletter_issue_progress(
+    postgres_client: &Object,
+    issue_id: Uuid,
+) -> Result<Option<NewsletterIssueProgress>, Error> {
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                SELECT title, status, total_recipients, delivered, failed, published_at, completed_at
+                FROM newsletter.newsletter_issues WHERE id = $1
+            "#,
+        )
+        .await?;
+    let row = match postgres_client.query_opt(&statement, &[&issue_id]).await? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let published_at: OffsetDateTime = row.get("published_at");
+    let completed_at: Option<OffsetDateTime> = row.get("completed_at");
+    Ok(Some(NewsletterIssueProgress {
+        issue_id,
+        title: row.get("title"),
+        status: row.get("status"),
+        total_recipients: row.get("total_recipients"),
+        delivered: row.get("delivered"),
+        failed: row.get("failed"),
+        published_at: to_rfc3339(published_at)?,
+        completed_at: completed_at.map(to_rfc3339).transpose()?,
+    }))
+}
@@ -0,0 +1,283 @@
+use crate::authentication::{require_scope, ApiKeyScope};
+use crate::postgres::PostgresRouter;
+use crate::readiness::to_rfc3339;
+use crate::routes::get_postgres_read_client;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use anyhow::{Error, Result};
+use deadpool_postgres::Object;
+use std::sync::Arc;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio_postgres::types::ToSql;
+use uuid::Uuid;
+
+pub static DEFAULT_SUBSCRIBERS_PAGE_SIZE: i64 = 50;
+pub static MAX_SUBSCRIBERS_PAGE_SIZE: i64 = 500;
+static SUBSCRIBER_STATUSES: [&str; 3] = ["pending_confirmation", "confirmed", "unsubscribed"];
+
+#[derive(serde::Deserialize)]
+pub struct SubscribersQuery {
+    pub status: Option<String>,
+    // Case-insensitive substring of the email
+    pub email: Option<String>,
+    // Case-insensitive substring of the name
+    pub name: Option<String>,
+    // RFC 3339 bounds of subscription_date, inclusive
+    pub subscribed_after: Option<String>,
+    pub subscribed_before: Option<String>,
+    // Id of the last subscriber of the previous page
+    pub cursor: Option<Uuid>,
+    pub limit: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberSummary {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscription_date: String,
+    pub unsubscribed_at: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscribersPage {
+    pub subscribers: Vec<SubscriberSummary>,
+    // Pass as `cursor` to fetch the following page, null on the last page
+    pub next_cursor: Option<Uuid>,
+}
+
+#[derive(Debug)]
+pub struct SubscribersFilter {
+    pub status: Option<String>,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub subscribed_after: Option<OffsetDateTime>,
+    pub subscribed_before: Option<OffsetDateTime>,
+    pub cursor: Option<Uuid>,
+    pub limit: i64,
+}
+
+// Conditions on parameters $1 to $6 of `SubscribersFilter::query_params`
+pub static SUBSCRIBERS_FILTER_CONDITIONS: &str = r#"
+    ($1::TEXT IS NULL OR status = $1)
+    AND ($2::TEXT IS NULL OR strpos(email, $2::CITEXT) > 0)
+    AND ($3::TEXT IS NULL OR strpos(lower(name), lower($3)) > 0)
+    AND ($4::TIMESTAMPTZ IS NULL OR subscription_date >= $4)
+    AND ($5::TIMESTAMPTZ IS NULL OR subscription_date <= $5)
+    AND ($6::UUID IS NULL OR id > $6)
+"#;
+
+impl SubscribersFilter {
+    pub fn query_params(&self) -> [&(dyn ToSql + Sync); 6] {
+        [
+            &self.status,
+            &self.email,
+            &self.name,
+            &self.subscribed_after,
+            &self.subscribed_before,
+            &self.cursor,
+        ]
+    }
+}
+
+fn parse_timestamp(
+    parameter: &str,
+    value: &Option<String>,
+) -> Result<Option<OffsetDateTime>, String> {
+    value
+        .as_ref()
+        .map(|value| {
+            OffsetDateTime::parse(value, &Rfc3339).map_err(|_| {
+                format!("Provided {parameter} \"{value}\" is not a valid RFC 3339 timestamp.")
+            })
+        })
+        .transpose()
+}
+
+fn non_blank(value: &Option<String>) -> Option<String> {
+    value
+        .as_ref()
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+pub fn parse_subscribers_query(query: &SubscribersQuery) -> Result<SubscribersFilter, String> {
+    let status = non_blank(&query.status);
+    if let Some(ref status) = status {
+        if !SUBSCRIBER_STATUSES.contains(&status.as_str()) {
+            return Err(format!(
+                "Provided status \"{status}\" is not one of {}.",
+                SUBSCRIBER_STATUSES.join(", ")
+            ));
+        }
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_SUBSCRIBERS_PAGE_SIZE);
+    if !(1..=MAX_SUBSCRIBERS_PAGE_SIZE).contains(&limit) {
+        return Err(format!(
+            "Provided limit {limit} must be between 1 and {MAX_SUBSCRIBERS_PAGE_SIZE}."
+        ));
+    }
+    Ok(SubscribersFilter {
+        status,
+        email: non_blank(&query.email),
+        name: non_blank(&query.name),
+        subscribed_after: parse_timestamp("subscribed_after", &query.subscribed_after)?,
+        subscribed_before: parse_timestamp("subscribed_before", &query.subscribed_before)?,
+        cursor: query.cursor,
+        limit,
+    })
+}
+
+#[tracing::instrument(name = "Listing subscribers.", skip(request, query))]
+pub async fn list_subscribers(
+    request: HttpRequest,
+    query: web::Query<SubscribersQuery>,
+) -> impl Responder {
+    if let Err(response) = require_scope(&request, ApiKeyScope::SubscribersRead) {
+        return response;
+    }
+    let filter = match parse_subscribers_query(&query) {
+        Ok(filter) => filter,
+        Err(error) => {
+            tracing::error!("routes/admin/subscribers.rs {}", error);
+            return HttpResponse::BadRequest().body(error);
+        }
+    };
+    let postgres_router = match request.app_data::<Arc<PostgresRouter>>() {
+        Some(postgres_router) => postgres_router,
+        None => {
+            tracing::error!("Could not retrieve postgres router from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("DB pool error while listing subscribers.");
+        }
+    };
+    let postgres_client = match get_postgres_read_client(postgres_router).await {
+        Some(postgres_client) => postgres_client,
+        None => {
+            return HttpResponse::InternalServerError()
+                .body("DB client error while listing subscribers.")
+        }
+    };
+    match get_subscribers_page(&postgres_client, &filter).await {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(error) => {
+            tracing::error!("Failed to list subscribers: {}", error);
+            HttpResponse::InternalServerError().body("DB error while listing subscribers.")
+        }
+    }
+}
+
+/// Keyset pagination over the primary key, UUIDv7 ids are ordered by creation time.
+#[tracing::instrument(name = "Querying subscribers page.", skip(postgres_client, filter))]
+pub async fn get_subscribers_page(
+    postgres_client: &Object,
+    filter: &SubscribersFilter,
+) -> Result<SubscribersPage, Error> {
+    // One extra row tells whether a following page exists
+    let fetch_limit = filter.limit + 1;
+    let statement = postgres_client
+        .prepare_cached(&format!(
+            r#"
+                SELECT id, email::TEXT AS email, name::TEXT AS name, status,
+                    subscription_date, unsubscribed_at
+                FROM newsletter.subscription
+                WHERE {}
+                ORDER BY id
+                LIMIT $7
+            "#,
+            SUBSCRIBERS_FILTER_CONDITIONS
+        ))
+        .await?;
+    let mut params = filter.query_params().to_vec();
+    params.push(&fetch_limit);
+    let rows = postgres_client.query(&statement, &params).await?;
+    let has_next_page = rows.len() as i64 > filter.limit;
+    let mut subscribers = Vec::with_capacity(rows.len());
+    for row in rows.iter().take(filter.limit as usize) {
+        let subscription_date: OffsetDateTime = row.get("subscription_date");
+        let unsubscribed_at: Option<OffsetDateTime> = row.get("unsubscribed_at");
+        subscribers.push(SubscriberSummary {
+            id: row.get("id"),
+            email: row.get("email"),
+            name: row.get("name"),
+            status: row.get("status"),
+            subscription_date: to_rfc3339(subscription_date)?,
+            unsubscribed_at: unsubscribed_at.map(to_rfc3339).transpose()?,
+        });
+    }
+    let next_cursor = if has_next_page {
+        subscribers.last().map(|subscriber| subscriber.id)
+    } else {
+        None
+    };
+    Ok(SubscribersPage {
+        subscribers,
+        next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::admin::{parse_subscribers_query, SubscribersQuery};
+    use claims::{assert_err, assert_ok};
+
+    fn query() -> SubscribersQuery {
+        SubscribersQuery {
+            status: None,
+            email: None,
+            name: None,
+            subscribed_after: None,
+            subscribed_before: None,
+            cursor: None,
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn empty_query_uses_default_limit() {
+        let filter = assert_ok!(parse_subscribers_query(&query()));
+        assert_eq!(50, filter.limit);
+        assert!(filter.status.is_none() && filter.email.is_none());
+    }
+
+    #[test]
+    fn blank_filters_are_ignored() {
+        let filter = assert_ok!(parse_subscribers_query(&SubscribersQuery {
+            email: Some("  ".to_owned()),
+            name: Some("".to_owned()),
+            ..query()
+        }));
+        assert!(filter.email.is_none() && filter.name.is_none());
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        assert_err!(parse_subscribers_query(&SubscribersQuery {
+            status: Some("deleted".to_owned()),
+            ..query()
+        }));
+        assert_err!(parse_subscribers_query(&SubscribersQuery {
+            limit: Some(0),
+            ..query()
+        }));
+        assert_err!(parse_subscribers_query(&SubscribersQuery {
+            limit: Some(501),
+            ..query()
+        }));
+        assert_err!(parse_subscribers_query(&SubscribersQuery {
+            subscribed_after: Some("yesterday".to_owned()),
+            ..query()
+        }));
+    }
+
+    #[test]
+    fn rfc3339_dates_are_parsed() {
+        let filter = assert_ok!(parse_subscribers_query(&SubscribersQuery {
+            subscribed_after: Some("2024-10-01T00:00:00Z".to_owned()),
+            subscribed_before: Some("2024-10-31T23:59:59+02:00".to_owned()),
+            ..query()
+        }));
+        assert!(filter.subscribed_after.unwrap() < filter.subscribed_before.unwrap());
+    }
+}
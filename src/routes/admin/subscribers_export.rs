@@ -0,0 +1,283 @@
+use crate::authentication::{require_scope, ApiKeyScope};
+use crate::postgres::PostgresRouter;
+use crate::readiness::to_rfc3339;
+use crate::routes::admin::{
+    parse_subscribers_query, SubscribersFilter, SubscribersQuery, SUBSCRIBERS_FILTER_CONDITIONS,
+};
+use crate::routes::get_postgres_read_client;
+use crate::startup::SubscriberExportPermits;
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use anyhow::{Error, Result};
+use deadpool_postgres::Object;
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+// Exports allowed to hold a pooled connection at the same time
+pub static MAX_CONCURRENT_SUBSCRIBER_EXPORTS: usize = 2;
+// Rows fetched from the cursor per chunk of the response body
+static EXPORT_FETCH_SQL: &str = "FETCH 1000 FROM subscribers_export";
+// Stalled downloads have their connection closed by the server rather than pinning it
+static EXPORT_IDLE_TIMEOUT: &str = "60s";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Jsonl,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ExportFormatQuery {
+    pub format: Option<ExportFormat>,
+}
+
+#[derive(serde::Serialize)]
+struct ExportedSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscription_date: String,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+    fn file_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Jsonl => "jsonl",
+        }
+    }
+    fn header(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "id,email,name,status,subscription_date\n",
+            ExportFormat::Jsonl => "",
+        }
+    }
+    fn write_row(&self, output: &mut String, row: &Row) -> Result<(), Error> {
+        let subscription_date: OffsetDateTime = row.get("subscription_date");
+        let subscriber = ExportedSubscriber {
+            id: row.get("id"),
+            email: row.get("email"),
+            name: row.get("name"),
+            status: row.get("status"),
+            subscription_date: to_rfc3339(subscription_date)?,
+        };
+        match self {
+            ExportFormat::Csv => {
+                let fields = [
+                    subscriber.id.to_string(),
+                    subscriber.email,
+                    subscriber.name,
+                    subscriber.status,
+                    subscriber.subscription_date,
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                output.push_str(&fields.join(","));
+            }
+            ExportFormat::Jsonl => output.push_str(&serde_json::to_string(&subscriber)?),
+        }
+        output.push('\n');
+        Ok(())
+    }
+}
+
+/// Quote fields containing separators, quotes or line breaks, doubling inner quotes.
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Export in progress, owning its pooled connection while the cursor is open.
+struct SubscribersExport {
+    postgres_client: Option<Object>,
+    format: ExportFormat,
+    header_pending: bool,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl SubscribersExport {
+    /// Next chunk of the body, `None` once the cursor is exhausted.
+    async fn next_chunk(&mut self) -> Result<Option<Bytes>, Error> {
+        let postgres_client = match self.postgres_client {
+            Some(ref postgres_client) => postgres_client,
+            None => return Ok(None),
+        };
+        let rows = postgres_client.query(EXPORT_FETCH_SQL, &[]).await?;
+        let mut chunk = String::new();
+        if self.header_pending {
+            chunk.push_str(self.format.header());
+            self.header_pending = false;
+        }
+        for row in &rows {
+            self.format.write_row(&mut chunk, row)?;
+        }
+        if rows.is_empty() {
+            // Closes the cursor, the connection goes back to the pool when dropped
+            postgres_client.batch_execute("COMMIT").await?;
+            self.postgres_client = None;
+            if chunk.is_empty() {
+                return Ok(None);
+            }
+        }
+        Ok(Some(Bytes::from(chunk)))
+    }
+    fn discard_connection(&mut self) {
+        if let Some(postgres_client) = self.postgres_client.take() {
+            // Detached from the pool, so the connection is closed along with its open transaction
+            drop(Object::take(postgres_client));
+        }
+    }
+}
+
+impl Drop for SubscribersExport {
+    // Download aborted by the client or failed mid-way
+    fn drop(&mut self) {
+        self.discard_connection();
+    }
+}
+
+/// Stream subscribers matching the listing filters as CSV or JSON Lines, reading them through a
+/// server-side cursor so that memory use doesn't grow with the number of subscribers.
+#[tracing::instrument(name = "Exporting subscribers.", skip(request, query, format))]
+pub async fn export_subscribers(
+    request: HttpRequest,
+    query: web::Query<SubscribersQuery>,
+    format: web::Query<ExportFormatQuery>,
+) -> impl Responder {
+    if let Err(response) = require_scope(&request, ApiKeyScope::SubscribersRead) {
+        return response;
+    }
+    let format = format.format.unwrap_or(ExportFormat::Csv);
+    let mut query = query.into_inner();
+    // Exports aren't paginated
+    query.limit = None;
+    let filter = match parse_subscribers_query(&query) {
+        Ok(filter) => filter,
+        Err(error) => {
+            tracing::error!("routes/admin/subscribers_export.rs {}", error);
+            return HttpResponse::BadRequest().body(error);
+        }
+    };
+    let permits = match request.app_data::<Arc<SubscriberExportPermits>>() {
+        Some(permits) => permits,
+        None => {
+            tracing::error!("Could not retrieve export permits from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("Configuration error while exporting subscribers.");
+        }
+    };
+    let permit = match permits.0.clone().try_acquire_owned() {
+        Ok(permit) => permit,
+        Err(_) => {
+            return HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, "30"))
+                .body("Too many subscriber exports in progress, retry later.")
+        }
+    };
+    let postgres_router = match request.app_data::<Arc<PostgresRouter>>() {
+        Some(postgres_router) => postgres_router,
+        None => {
+            tracing::error!("Could not retrieve postgres router from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("DB pool error while exporting subscribers.");
+        }
+    };
+    let postgres_client = match get_postgres_read_client(postgres_router).await {
+        Some(postgres_client) => postgres_client,
+        None => {
+            return HttpResponse::InternalServerError()
+                .body("DB client error while exporting subscribers.")
+        }
+    };
+    let export = SubscribersExport {
+        postgres_client: Some(postgres_client),
+        format,
+        header_pending: true,
+        _permit: permit,
+    };
+    if let Err(error) = open_export_cursor(export.postgres_client.as_ref().unwrap(), &filter).await
+    {
+        tracing::error!("Failed to open subscribers export cursor: {}", error);
+        return HttpResponse::InternalServerError().body("DB error while exporting subscribers.");
+    }
+    let body = futures::stream::unfold(export, |mut export| async move {
+        match export.next_chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), export)),
+            Ok(None) => None,
+            Err(error) => {
+                tracing::error!("Failed to export subscribers: {}", error);
+                export.discard_connection();
+                Some((Err(error), export))
+            }
+        }
+    });
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"subscribers.{}\"",
+                format.file_extension()
+            ),
+        ))
+        .streaming(body)
+}
+
+async fn open_export_cursor(
+    postgres_client: &Object,
+    filter: &SubscribersFilter,
+) -> Result<(), tokio_postgres::Error> {
+    postgres_client
+        .batch_execute(&format!(
+            "BEGIN READ ONLY; SET LOCAL idle_in_transaction_session_timeout = '{}'",
+            EXPORT_IDLE_TIMEOUT
+        ))
+        .await?;
+    postgres_client
+        .execute(
+            &format!(
+                r#"
+                    DECLARE subscribers_export NO SCROLL CURSOR FOR
+                    SELECT id, email::TEXT AS email, name::TEXT AS name, status, subscription_date
+                    FROM newsletter.subscription
+                    WHERE {}
+                    ORDER BY id
+                "#,
+                SUBSCRIBERS_FILTER_CONDITIONS
+            ),
+            &filter.query_params(),
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::admin::csv_field;
+
+    #[test]
+    fn plain_csv_fields_are_unquoted() {
+        assert_eq!("jane@drconopoima.com", csv_field("jane@drconopoima.com"));
+    }
+
+    #[test]
+    fn special_csv_fields_are_quoted() {
+        assert_eq!("\"Smith, John\"", csv_field("Smith, John"));
+        assert_eq!("\"The \"\"Boss\"\"\"", csv_field("The \"Boss\""));
+        assert_eq!("\"two\nlines\"", csv_field("two\nlines"));
+    }
+}
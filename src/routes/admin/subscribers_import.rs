@@ -0,0 +1,43 @@
+use crate::authentication::require_admin_session;
+use crate::routes::get_postgres_client;
+use crate::subscription::{import_subscribers, SubscriberImportError};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use deadpool_postgres::Pool;
+use std::sync::Arc;
+
+/// Import a CSV upload of `email,name` rows, streamed without buffering the whole body.
+#[tracing::instrument(name = "Importing subscribers.", skip(request, payload))]
+pub async fn import_admin_subscribers(
+    request: HttpRequest,
+    payload: web::Payload,
+) -> impl Responder {
+    if let Err(response) = require_admin_session(&request) {
+        return response;
+    }
+    let postgres_pool = match request.app_data::<Arc<Pool>>() {
+        Some(postgres_pool) => postgres_pool,
+        None => {
+            tracing::error!("Could not retrieve postgres pool from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("DB pool error while importing subscribers.");
+        }
+    };
+    let mut postgres_client = match get_postgres_client(postgres_pool).await {
+        Some(postgres_client) => postgres_client,
+        None => {
+            return HttpResponse::InternalServerError()
+                .body("DB client error while importing subscribers.")
+        }
+    };
+    match import_subscribers(&mut postgres_client, payload).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(error @ SubscriberImportError::Database(_)) => {
+            tracing::error!("Failed to import subscribers: {}", error);
+            HttpResponse::InternalServerError().body("DB error while importing subscribers.")
+        }
+        Err(error) => {
+            tracing::error!("routes/admin/subscribers_import.rs {}", error);
+            HttpResponse::BadRequest().body(error.to_string())
+        }
+    }
+}
@@ -1,5 +1,10 @@
+pub mod admin;
 mod healthcheck;
 mod subscription;
+mod subscription_confirm;
+mod subscription_unsubscribe;
 
 pub use healthcheck::*;
 pub use subscription::*;
+pub use subscription_confirm::*;
+pub use subscription_unsubscribe::*;
@@ -1,11 +1,19 @@
-use crate::subscription::{FormData, SubscriptionFormData};
+use crate::email_client::{EmailClient, EmailMessage};
+use crate::postgres::{is_read_only_error, PostgresRouter};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::subscription::{FormData, SubscriptionFormData, UnsubscribeToken};
+use actix_web::http::header;
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
-use deadpool_postgres::{Object, Pool};
+use deadpool_postgres::{Object, Pool, Transaction};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use std::convert::TryFrom;
 use std::sync::Arc;
+use tokio_postgres::error::SqlState;
 use tokio_postgres::Statement;
 use uuid::{NoContext, Timestamp, Uuid};
 
+pub static SUBSCRIPTION_TOKEN_LENGTH: usize = 25;
+
 fn parse_subscription_form_data(form: FormData) -> Result<SubscriptionFormData, String> {
     SubscriptionFormData::try_from(form)
 }
@@ -37,14 +45,89 @@ pub async fn subscription(request: HttpRequest, form: web::Form<FormData>) -> im
         return HttpResponse::InternalServerError()
             .body("DB pool error while processing subscription.");
     }
+    let email_client = match request.app_data::<Arc<dyn EmailClient>>() {
+        Some(email_client) => email_client,
+        None => {
+            tracing::error!("Could not retrieve email client from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("Email client error while processing subscription.");
+        }
+    };
+    let base_url = match request.app_data::<Arc<ApplicationBaseUrl>>() {
+        Some(base_url) => base_url,
+        None => {
+            tracing::error!("Could not retrieve application base URL from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("Configuration error while processing subscription.");
+        }
+    };
+    let hmac_secret = match request.app_data::<Arc<HmacSecret>>() {
+        Some(hmac_secret) => hmac_secret,
+        None => {
+            tracing::error!("Could not retrieve HMAC secret from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("Configuration error while processing subscription.");
+        }
+    };
     let postgres_pool = optional_postgres_pool.unwrap();
     let optional_postgres_client = get_postgres_client(postgres_pool).await;
     if optional_postgres_client.is_none() {
         return HttpResponse::InternalServerError()
             .body("DB client error while processing subscription.");
     }
-    let postgres_client = optional_postgres_client.unwrap();
-    run_insert_subscriber_query(postgres_client, subscription_form).await
+    let mut postgres_client = optional_postgres_client.unwrap();
+    let transaction = match postgres_client.transaction().await {
+        Ok(transaction) => transaction,
+        Err(error) => {
+            tracing::error!("Failed to begin subscription transaction: {}", error);
+            return HttpResponse::InternalServerError()
+                .body("DB transaction error while processing subscription.");
+        }
+    };
+    let subscriber_id = match run_insert_subscriber_query(&transaction, &subscription_form).await {
+        Ok(subscriber_id) => subscriber_id,
+        Err(InsertSubscriberError::ReadOnlyNode) => {
+            // Connected node was demoted, reconnect to the new primary for the next requests
+            drop(transaction);
+            match request.app_data::<Arc<PostgresRouter>>() {
+                Some(postgres_router) => postgres_router.drain_primary(
+                    Some(postgres_client),
+                    "inserting subscription failed on a read-only node",
+                ),
+                None => tracing::error!("Could not retrieve postgres router from app_data."),
+            }
+            return HttpResponse::ServiceUnavailable()
+                .insert_header((header::RETRY_AFTER, "5"))
+                .body("DB failover in progress, retry later.");
+        }
+        Err(InsertSubscriberError::Response(response)) => return response,
+    };
+    let subscription_token = generate_subscription_token();
+    if let Err(response) =
+        run_insert_subscription_token_query(&transaction, subscriber_id, &subscription_token).await
+    {
+        return response;
+    }
+    // Send before committing so that a delivery failure doesn't leave behind a pending subscriber
+    if let Err(error) = send_confirmation_email(
+        email_client.as_ref(),
+        &subscription_form,
+        &base_url.0,
+        &subscription_token,
+        &UnsubscribeToken::sign(subscriber_id, &hmac_secret.0),
+    )
+    .await
+    {
+        tracing::error!("Failed to send confirmation email: {:?}", error);
+        return HttpResponse::InternalServerError()
+            .body("Email error while sending subscription confirmation.");
+    }
+    if let Err(error) = transaction.commit().await {
+        tracing::error!("Failed to commit subscription transaction: {}", error);
+        return HttpResponse::InternalServerError()
+            .body("DB transaction error while processing subscription.");
+    }
+    HttpResponse::Ok().finish()
 }
 
 #[tracing::instrument(name = "Retrieving database client from pool.", skip(postgres_pool))]
@@ -58,16 +141,32 @@ pub async fn get_postgres_client(postgres_pool: &Arc<Pool>) -> Option<Object> {
     }
 }
 
+#[tracing::instrument(name = "Retrieving read-only database client.", skip(postgres_router))]
+pub async fn get_postgres_read_client(postgres_router: &Arc<PostgresRouter>) -> Option<Object> {
+    match postgres_router.get_read_client().await {
+        Ok((postgres_client, _)) => Some(postgres_client),
+        Err(error) => {
+            tracing::error!("Could not retrieve postgres read client, {}.", error);
+            None
+        }
+    }
+}
+
 #[tracing::instrument(
     name = "Preparing cached insert subscription query statement.",
-    skip(postgres_client)
+    skip(transaction)
 )]
-pub async fn prepare_cached_statement(postgres_client: &Object) -> Option<Statement> {
-    match postgres_client
+pub async fn prepare_cached_statement(transaction: &Transaction<'_>) -> Option<Statement> {
+    match transaction
         .prepare_cached(
             r#"
-                INSERT INTO newsletter.subscription (id, email, name)
-                VALUES ($1, $2, $3)
+                INSERT INTO newsletter.subscription (id, email, name, status)
+                VALUES ($1, $2, $3, 'pending_confirmation')
+                ON CONFLICT (email) DO UPDATE
+                    SET name = EXCLUDED.name, status = 'pending_confirmation',
+                        subscription_date = now(), unsubscribed_at = NULL
+                    WHERE subscription.status = 'unsubscribed'
+                RETURNING id
             "#,
         )
         .await
@@ -83,39 +182,137 @@ pub async fn prepare_cached_statement(postgres_client: &Object) -> Option<Statem
     }
 }
 
+/// Failure to insert a subscriber, telling writes refused by a demoted node apart so that the
+/// handler can drain the connections to it.
+pub enum InsertSubscriberError {
+    ReadOnlyNode,
+    Response(HttpResponse),
+}
+
 #[tracing::instrument(
     "Running insert query to save subscription into database.",
-    skip(postgres_client, form)
+    skip(transaction, form)
 )]
 pub async fn run_insert_subscriber_query(
-    postgres_client: Object,
-    form: SubscriptionFormData,
-) -> HttpResponse {
-    let statement = prepare_cached_statement(&postgres_client).await;
+    transaction: &Transaction<'_>,
+    form: &SubscriptionFormData,
+) -> Result<Uuid, InsertSubscriberError> {
+    let statement = prepare_cached_statement(transaction).await;
     if statement.is_none() {
-        return HttpResponse::InternalServerError().finish();
+        return Err(InsertSubscriberError::Response(
+            HttpResponse::InternalServerError().finish(),
+        ));
     }
     let generated_uuid: Uuid = Uuid::new_v7(Timestamp::now(NoContext));
-    match postgres_client
-        .query(
+    let already_subscribed = || {
+        InsertSubscriberError::Response(HttpResponse::BadRequest().body(format!(
+            "Input error, email '{}' is already subscribed.",
+            &form.email
+        )))
+    };
+    // Subscribers who left sign up again under their former id, others are left untouched
+    match transaction
+        .query_opt(
             &statement.unwrap(),
             &[&generated_uuid, &form.email.as_ref(), &form.name.as_ref()],
         )
         .await
     {
-        Ok(_) => HttpResponse::Ok().finish(),
+        Ok(Some(row)) => Ok(row.get("id")),
+        Ok(None) => Err(already_subscribed()),
         Err(error) => {
             tracing::warn!("Failed to insert subscription: {}", error);
-            let error_message = error.to_string();
-            if error_message
-                .starts_with("db error: ERROR: duplicate key value violates unique constraint")
-            {
-                return HttpResponse::BadRequest().body(format!(
-                    "Input error, email '{}' is already subscribed.",
-                    &form.email
-                ));
+            if is_read_only_error(&error) {
+                return Err(InsertSubscriberError::ReadOnlyNode);
             }
-            HttpResponse::InternalServerError().body("DB error while inserting subscription")
+            if error.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+                return Err(already_subscribed());
+            }
+            Err(InsertSubscriberError::Response(
+                HttpResponse::InternalServerError().body("DB error while inserting subscription"),
+            ))
         }
     }
 }
+
+#[tracing::instrument(
+    "Running insert query to save subscription token into database.",
+    skip(transaction, subscription_token)
+)]
+pub async fn run_insert_subscription_token_query(
+    transaction: &Transaction<'_>,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), HttpResponse> {
+    let statement = match transaction
+        .prepare_cached(
+            r#"
+                INSERT INTO newsletter.subscription_tokens (subscription_token, subscriber_id)
+                VALUES ($1, $2)
+            "#,
+        )
+        .await
+    {
+        Ok(statement) => statement,
+        Err(error) => {
+            tracing::error!(
+                "Failed to prepare cached insert subscription token query: {}",
+                error
+            );
+            return Err(HttpResponse::InternalServerError().finish());
+        }
+    };
+    match transaction
+        .query(&statement, &[&subscription_token, &subscriber_id])
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(error) => {
+            tracing::warn!("Failed to insert subscription token: {}", error);
+            Err(HttpResponse::InternalServerError()
+                .body("DB error while inserting subscription token"))
+        }
+    }
+}
+
+/// Random alphanumeric token, case-sensitive, sent to the subscriber to confirm their address.
+pub fn generate_subscription_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(SUBSCRIPTION_TOKEN_LENGTH)
+        .collect()
+}
+
+#[tracing::instrument(
+    name = "Sending subscription confirmation email.",
+    skip(email_client, form, base_url, subscription_token, unsubscribe_token)
+)]
+pub async fn send_confirmation_email(
+    email_client: &dyn EmailClient,
+    form: &SubscriptionFormData,
+    base_url: &str,
+    subscription_token: &str,
+    unsubscribe_token: &UnsubscribeToken,
+) -> Result<(), anyhow::Error> {
+    let confirmation_link = format!(
+        "{}/subscription/confirm?token={}",
+        base_url.trim_end_matches('/'),
+        subscription_token
+    );
+    let message = EmailMessage {
+        to: form.email.clone(),
+        subject: "Please confirm your newsletter subscription".to_owned(),
+        html_body: format!(
+            "Welcome to our newsletter, {}!<br />\
+            Click <a href=\"{}\">here</a> to confirm your subscription.",
+            form.name, confirmation_link
+        ),
+        text_body: format!(
+            "Welcome to our newsletter, {}!\nVisit {} to confirm your subscription.",
+            form.name, confirmation_link
+        ),
+        unsubscribe_link: unsubscribe_token.link(base_url),
+    };
+    email_client.send_email(&message).await
+}
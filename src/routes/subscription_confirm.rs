@@ -0,0 +1,96 @@
+use crate::routes::{get_postgres_client, SUBSCRIPTION_TOKEN_LENGTH};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use deadpool_postgres::{Object, Pool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmationParameters {
+    pub token: String,
+}
+
+fn is_valid_subscription_token(token: &str) -> bool {
+    token.len() == SUBSCRIPTION_TOKEN_LENGTH && token.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[tracing::instrument(name = "Confirming pending subscriber.", skip(parameters, request))]
+pub async fn subscription_confirm(
+    request: HttpRequest,
+    parameters: web::Query<ConfirmationParameters>,
+) -> impl Responder {
+    if !is_valid_subscription_token(&parameters.token) {
+        return HttpResponse::BadRequest().body("Input error, malformed subscription token.");
+    }
+    let postgres_pool = match request.app_data::<Arc<Pool>>() {
+        Some(postgres_pool) => postgres_pool,
+        None => {
+            tracing::error!("Could not retrieve postgres pool from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("DB pool error while confirming subscription.");
+        }
+    };
+    let postgres_client = match get_postgres_client(postgres_pool).await {
+        Some(postgres_client) => postgres_client,
+        None => {
+            return HttpResponse::InternalServerError()
+                .body("DB client error while confirming subscription.")
+        }
+    };
+    let subscriber_id =
+        match get_subscriber_id_from_token(&postgres_client, &parameters.token).await {
+            Ok(Some(subscriber_id)) => subscriber_id,
+            Ok(None) => {
+                return HttpResponse::Unauthorized().body("Unknown subscription token.");
+            }
+            Err(error) => {
+                tracing::error!("Failed to retrieve subscriber from token: {}", error);
+                return HttpResponse::InternalServerError()
+                    .body("DB error while confirming subscription.");
+            }
+        };
+    match confirm_subscriber(&postgres_client, subscriber_id).await {
+        Ok(_) => HttpResponse::Ok().finish(),
+        Err(error) => {
+            tracing::error!("Failed to confirm subscriber: {}", error);
+            HttpResponse::InternalServerError().body("DB error while confirming subscription.")
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Retrieving subscriber id from subscription token.",
+    skip(postgres_client, subscription_token)
+)]
+pub async fn get_subscriber_id_from_token(
+    postgres_client: &Object,
+    subscription_token: &str,
+) -> Result<Option<Uuid>, tokio_postgres::Error> {
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                SELECT subscriber_id FROM newsletter.subscription_tokens
+                WHERE subscription_token = $1
+            "#,
+        )
+        .await?;
+    let row = postgres_client
+        .query_opt(&statement, &[&subscription_token])
+        .await?;
+    Ok(row.map(|row| row.get("subscriber_id")))
+}
+
+#[tracing::instrument(name = "Marking subscriber as confirmed.", skip(postgres_client))]
+pub async fn confirm_subscriber(
+    postgres_client: &Object,
+    subscriber_id: Uuid,
+) -> Result<u64, tokio_postgres::Error> {
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                UPDATE newsletter.subscription SET status = 'confirmed'
+                WHERE id = $1 AND status = 'pending_confirmation'
+            "#,
+        )
+        .await?;
+    postgres_client.execute(&statement, &[&subscriber_id]).await
+}
@@ -0,0 +1,127 @@
+use crate::routes::get_postgres_client;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::subscription::{UnsubscribeToken, UnsubscribeTokenError};
+use actix_web::{http::header::ContentType, web, HttpRequest, HttpResponse, Responder};
+use deadpool_postgres::{Object, Pool};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    pub token: String,
+}
+
+fn verify_unsubscribe_token(request: &HttpRequest, token: &str) -> Result<Uuid, HttpResponse> {
+    let hmac_secret = match request.app_data::<Arc<HmacSecret>>() {
+        Some(hmac_secret) => hmac_secret,
+        None => {
+            tracing::error!("Could not retrieve HMAC secret from app_data.");
+            return Err(HttpResponse::InternalServerError()
+                .body("Configuration error while processing unsubscription."));
+        }
+    };
+    UnsubscribeToken::verify(token, &hmac_secret.0).map_err(|error| match error {
+        UnsubscribeTokenError::Malformed => {
+            HttpResponse::BadRequest().body(format!("Input error, {}", error))
+        }
+        UnsubscribeTokenError::InvalidSignature => {
+            HttpResponse::Unauthorized().body(error.to_string())
+        }
+    })
+}
+
+// Following the link must not unsubscribe by itself, mail scanners prefetch links.
+// The page asks for confirmation by submitting the same one-click POST as RFC 8058 clients
+#[tracing::instrument(
+    name = "Rendering unsubscribe confirmation.",
+    skip(parameters, request)
+)]
+pub async fn subscription_unsubscribe_form(
+    request: HttpRequest,
+    parameters: web::Query<UnsubscribeParameters>,
+) -> impl Responder {
+    let subscriber_id = match verify_unsubscribe_token(&request, &parameters.token) {
+        Ok(subscriber_id) => subscriber_id,
+        Err(response) => return response,
+    };
+    let (base_url, hmac_secret) = match (
+        request.app_data::<Arc<ApplicationBaseUrl>>(),
+        request.app_data::<Arc<HmacSecret>>(),
+    ) {
+        (Some(base_url), Some(hmac_secret)) => (base_url, hmac_secret),
+        _ => {
+            tracing::error!("Could not retrieve application base URL from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("Configuration error while processing unsubscription.");
+        }
+    };
+    // Same link as in the emails, which honours a base URL with a path prefix. The token is
+    // signed again in its canonical form, which only contains hexadecimal digits and a dot
+    let unsubscribe_link = UnsubscribeToken::sign(subscriber_id, &hmac_secret.0).link(&base_url.0);
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><title>Unsubscribe</title></head>
+<body>
+<form action="{}" method="post">
+<input type="hidden" name="List-Unsubscribe" value="One-Click">
+<button type="submit">Unsubscribe from the newsletter</button>
+</form>
+</body>
+</html>"#,
+            unsubscribe_link
+        ))
+}
+
+#[tracing::instrument(name = "Unsubscribing subscriber.", skip(parameters, request))]
+pub async fn subscription_unsubscribe(
+    request: HttpRequest,
+    parameters: web::Query<UnsubscribeParameters>,
+) -> impl Responder {
+    let subscriber_id = match verify_unsubscribe_token(&request, &parameters.token) {
+        Ok(subscriber_id) => subscriber_id,
+        Err(response) => return response,
+    };
+    let postgres_pool = match request.app_data::<Arc<Pool>>() {
+        Some(postgres_pool) => postgres_pool,
+        None => {
+            tracing::error!("Could not retrieve postgres pool from app_data.");
+            return HttpResponse::InternalServerError()
+                .body("DB pool error while processing unsubscription.");
+        }
+    };
+    let postgres_client = match get_postgres_client(postgres_pool).await {
+        Some(postgres_client) => postgres_client,
+        None => {
+            return HttpResponse::InternalServerError()
+                .body("DB client error while processing unsubscription.")
+        }
+    };
+    // Repeated requests for the same token are successful, one-click clients may retry
+    match unsubscribe_subscriber(&postgres_client, subscriber_id).await {
+        Ok(_) => HttpResponse::Ok().body("You have been unsubscribed from the newsletter."),
+        Err(error) => {
+            tracing::error!("Failed to unsubscribe subscriber: {}", error);
+            HttpResponse::InternalServerError().body("DB error while processing unsubscription.")
+        }
+    }
+}
+
+#[tracing::instrument(name = "Marking subscriber as unsubscribed.", skip(postgres_client))]
+pub async fn unsubscribe_subscriber(
+    postgres_client: &Object,
+    subscriber_id: Uuid,
+) -> Result<u64, tokio_postgres::Error> {
+    let statement = postgres_client
+        .prepare_cached(
+            r#"
+                UPDATE newsletter.subscription
+                SET status = 'unsubscribed', unsubscribed_at = now()
+                WHERE id = $1 AND status <> 'unsubscribed'
+            "#,
+        )
+        .await?;
+    postgres_client.execute(&statement, &[&subscriber_id]).await
+}
@@ -1,21 +1,62 @@
+use crate::authentication::reject_anonymous_users;
+use crate::email_client::EmailClient;
+use crate::postgres::PostgresRouter;
 use crate::readiness::{probe_readiness, CachedHealth};
-use crate::routes::{healthcheck, subscription};
-use actix_web::{dev::Server, web, App, HttpServer};
-use anyhow::{Context, Result};
+use crate::routes::admin::{
+    admin_login, admin_logout, create_admin_api_key, export_subscribers, import_admin_subscribers,
+    list_admin_api_keys, list_subscribers, newsletter_progress, publish_newsletter,
+    revoke_admin_api_key, MAX_CONCURRENT_SUBSCRIBER_EXPORTS,
+};
+use crate::routes::{
+    healthcheck, subscription, subscription_confirm, subscription_unsubscribe,
+    subscription_unsubscribe_form,
+};
+use actix_web::{dev::Server, middleware::from_fn, web, App, HttpServer};
+use anyhow::Result;
 use deadpool_postgres::Pool;
+use secrecy::SecretString;
 use std::net::TcpListener;
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::time::Duration;
+use tokio::sync::Semaphore;
 use tracing_actix_web::TracingLogger;
 
+// Public URL prefix of the application, used to build links sent by email
+pub struct ApplicationBaseUrl(pub String);
+
+// Key signing and verifying unsubscribe tokens
+pub struct HmacSecret(pub SecretString);
+
+// Lifetime of sessions issued by the admin login
+pub struct AdminSessionTtl(pub Duration);
+
+// Bounds subscriber exports streaming at once, each holding a pooled connection
+pub struct SubscriberExportPermits(pub Arc<Semaphore>);
+
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     listener: TcpListener,
     postgres_pool: Pool,
-    admin_bind_address: Option<(String, u16)>,
+    postgres_replica_pool: Option<Pool>,
+    admin_listener: Option<TcpListener>,
     healthcheck_validity_period_ms: Option<Duration>,
+    email_client: Arc<dyn EmailClient>,
+    base_url: String,
+    hmac_secret: SecretString,
+    admin_session_ttl: Duration,
 ) -> Result<(Server, Option<Server>)> {
+    let postgres_router = Arc::new(PostgresRouter::new(
+        postgres_pool.clone(),
+        postgres_replica_pool,
+    ));
     let postgres_pool = Arc::new(postgres_pool);
+    let base_url = Arc::new(ApplicationBaseUrl(base_url));
+    let hmac_secret = Arc::new(HmacSecret(hmac_secret));
+    let admin_session_ttl = Arc::new(AdminSessionTtl(admin_session_ttl));
+    let export_permits = Arc::new(SubscriberExportPermits(Arc::new(Semaphore::new(
+        MAX_CONCURRENT_SUBSCRIBER_EXPORTS,
+    ))));
     let healthcheck_validity_period: Duration =
         if let Some(healthcheck_validity) = healthcheck_validity_period_ms {
             healthcheck_validity
@@ -25,16 +66,16 @@ pub fn run(
     let cached_healthcheck = CachedHealth(None);
     let arc_cached_healthcheck: Arc<RwLock<CachedHealth>> =
         Arc::new(RwLock::from(cached_healthcheck));
-    if admin_bind_address.is_none() {
+    if admin_listener.is_none() {
         let server = HttpServer::new(move || {
             let arc_cached_healthcheck_readiness = arc_cached_healthcheck.clone();
-            let postgres_pool_readiness = postgres_pool.clone();
+            let postgres_router_readiness = postgres_router.clone();
             tokio::task::spawn_blocking(move || {
                 let mut interval = tokio::time::interval(healthcheck_validity_period);
                 loop {
                     futures::executor::block_on(interval.tick());
                     let healthresponse = futures::executor::block_on(probe_readiness(
-                        postgres_pool_readiness.clone(),
+                        postgres_router_readiness.clone(),
                     ));
                     if let Ok(mut cache) = arc_cached_healthcheck_readiness.write() {
                         cache.0 = Some(healthresponse);
@@ -48,45 +89,71 @@ pub fn run(
                 .route("/healthcheck", web::get().to(healthcheck))
                 // Handle newsletter subscription requests
                 .route("/subscription", web::post().to(subscription))
+                // Handle confirmation links sent by email to new subscribers
+                .route("/subscription/confirm", web::get().to(subscription_confirm))
+                // Handle signed unsubscribe links, POST is the RFC 8058 one-click action
+                .route(
+                    "/subscription/unsubscribe",
+                    web::get().to(subscription_unsubscribe_form),
+                )
+                .route(
+                    "/subscription/unsubscribe",
+                    web::post().to(subscription_unsubscribe),
+                )
                 // Register the Postgres connection as part of application state
                 .app_data(postgres_pool.clone())
+                .app_data(postgres_router.clone())
                 // Register cache for healthcheck endpoint
                 .app_data(arc_cached_healthcheck.clone())
+                // Register email delivery backend, base URL and key for links sent by email
+                .app_data(email_client.clone())
+                .app_data(base_url.clone())
+                .app_data(hmac_secret.clone())
         })
         .listen(listener)?
         .run();
         return Ok((server, None));
     }
-    let admin_bind_address = admin_bind_address.unwrap();
-    let admin_listener = TcpListener::bind(&admin_bind_address).with_context(|| {
-        format!(
-            "{}::startup::run: Failed to open a TCP Listener on address '{}' and port '{}'.",
-            env!("CARGO_PKG_NAME"),
-            admin_bind_address.0,
-            admin_bind_address.1
-        )
-    })?;
+    let admin_listener = admin_listener.unwrap();
     let postgres_pool1 = postgres_pool.clone();
+    let postgres_router1 = postgres_router.clone();
+    let hmac_secret1 = hmac_secret.clone();
     let server1 = HttpServer::new(move || {
         App::new()
             // Logging middleware
             .wrap(TracingLogger::default())
             // Handle newsletter subscription requests
             .route("/subscription", web::post().to(subscription))
+            // Handle confirmation links sent by email to new subscribers
+            .route("/subscription/confirm", web::get().to(subscription_confirm))
+            // Handle signed unsubscribe links, POST is the RFC 8058 one-click action
+            .route(
+                "/subscription/unsubscribe",
+                web::get().to(subscription_unsubscribe_form),
+            )
+            .route(
+                "/subscription/unsubscribe",
+                web::post().to(subscription_unsubscribe),
+            )
             // Register the Postgres connection as part of application state
             .app_data(postgres_pool1.clone())
+            .app_data(postgres_router1.clone())
+            // Register email delivery backend, base URL and key for links sent by email
+            .app_data(email_client.clone())
+            .app_data(base_url.clone())
+            .app_data(hmac_secret1.clone())
     })
     .listen(listener)?
     .run();
     let server2 = HttpServer::new(move || {
         let arc_cached_healthcheck_readiness = arc_cached_healthcheck.clone();
-        let postgres_pool_readiness = postgres_pool.clone();
+        let postgres_router_readiness = postgres_router.clone();
         tokio::task::spawn_blocking(move || {
             let mut interval = tokio::time::interval(healthcheck_validity_period);
             loop {
                 futures::executor::block_on(interval.tick());
                 let healthresponse =
-                    futures::executor::block_on(probe_readiness(postgres_pool_readiness.clone()));
+                    futures::executor::block_on(probe_readiness(postgres_router_readiness.clone()));
                 if let Ok(mut cache) = arc_cached_healthcheck_readiness.write() {
                     cache.0 = Some(healthresponse);
                 }
@@ -97,10 +164,43 @@ pub fn run(
             .wrap(TracingLogger::default())
             // Ensure App to be running correctly
             .route("/healthcheck", web::get().to(healthcheck))
-            // Register the Postgres connection as part of application state
+            // Issue session cookies, the only admin route open to anonymous users
+            .route("/admin/login", web::post().to(admin_login))
+            .service(
+                web::scope("/admin")
+                    // Every other admin route requires a valid session or API key
+                    .wrap(from_fn(reject_anonymous_users))
+                    .route("/logout", web::post().to(admin_logout))
+                    // Manage API keys of machine clients, authenticated by bearer tokens
+                    .route("/api-keys", web::post().to(create_admin_api_key))
+                    .route("/api-keys", web::get().to(list_admin_api_keys))
+                    .route("/api-keys/{key_id}", web::delete().to(revoke_admin_api_key))
+                    // Browse subscribers with keyset pagination, or stream them all, under filters
+                    .route("/subscribers", web::get().to(list_subscribers))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    // Bulk import subscribers migrated from another provider
+                    .route(
+                        "/subscribers/import",
+                        web::post().to(import_admin_subscribers),
+                    )
+                    // Publish newsletter issues to confirmed subscribers and poll their delivery
+                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route(
+                        "/newsletters/{issue_id}",
+                        web::get().to(newsletter_progress),
+                    ),
+            )
+            // Register the Postgres connection as part of application state, and the router
+            // sending read-only work to replicas
             .app_data(postgres_pool.clone())
+            .app_data(postgres_router.clone())
             // Register cache for healthcheck endpoint
             .app_data(arc_cached_healthcheck.clone())
+            // Register key signing session cookies and their lifetime
+            .app_data(hmac_secret.clone())
+            .app_data(admin_session_ttl.clone())
+            // Register the limit of concurrent subscriber exports
+            .app_data(export_permits.clone())
     })
     .listen(admin_listener)?
     .run();
@@ -1,8 +1,14 @@
+mod subscriber_import;
 mod subscription_filtered_email;
 mod subscription_filtered_name;
 mod subscription_form_data;
+mod unsubscribe_token;
 
+pub use subscriber_import::{
+    import_subscribers, ImportRejection, ImportReport, SubscriberImportError,
+};
 pub use subscription_filtered_email::SubscriptionFilteredEmail;
 pub use subscription_filtered_name::SubscriptionFilteredName;
 pub use subscription_form_data::FormData;
 pub use subscription_form_data::SubscriptionFormData;
+pub use unsubscribe_token::{UnsubscribeToken, UnsubscribeTokenError};
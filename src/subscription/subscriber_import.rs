@@ -0,0 +1,353 @@
+use crate::subscription::{SubscriptionFilteredEmail, SubscriptionFilteredName};
+use csv_core::{ReadRecordResult, Reader};
+use deadpool_postgres::Object;
+use futures::{pin_mut, Stream, StreamExt};
+use std::fmt;
+use std::string::FromUtf8Error;
+use std::time::Duration;
+use tokio_postgres::binary_copy::BinaryCopyInWriter;
+use tokio_postgres::types::Type;
+use uuid::{NoContext, Timestamp, Uuid};
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct ImportRejection {
+    // Row of the CSV upload, the header being row 1
+    pub row: u64,
+    pub email: String,
+    pub reason: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub rejected: Vec<ImportRejection>,
+}
+
+#[derive(Debug)]
+pub enum SubscriberImportError {
+    InvalidCsv(String),
+    Upload(String),
+    Database(tokio_postgres::Error),
+}
+
+impl fmt::Display for SubscriberImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubscriberImportError::InvalidCsv(error) => write!(f, "Invalid CSV upload: {}", error),
+            SubscriberImportError::Upload(error) => write!(f, "Failed to read upload: {}", error),
+            SubscriberImportError::Database(error) => {
+                write!(f, "DB error while importing subscribers: {}", error)
+            }
+        }
+    }
+}
+
+impl From<tokio_postgres::Error> for SubscriberImportError {
+    fn from(error: tokio_postgres::Error) -> Self {
+        SubscriberImportError::Database(error)
+    }
+}
+
+// Stalled uploads are abandoned rather than pinning a pooled connection in a transaction. Time
+// spent waiting within COPY doesn't count as idle in transaction for the server
+static IMPORT_STALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+type CsvRecord = Result<Vec<String>, FromUtf8Error>;
+
+/// Incremental CSV parser, fed with the chunks of an upload as they arrive.
+struct CsvRecords {
+    reader: Reader,
+    output: Vec<u8>,
+    output_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+}
+
+impl CsvRecords {
+    fn new() -> Self {
+        Self {
+            reader: Reader::new(),
+            output: vec![0; 1024],
+            output_len: 0,
+            ends: vec![0; 16],
+            ends_len: 0,
+        }
+    }
+    /// Return the records completed by `input`. An empty `input` marks the end of the upload.
+    fn feed(&mut self, mut input: &[u8]) -> Vec<CsvRecord> {
+        let mut records = Vec::new();
+        loop {
+            let (result, read, written, ended) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[read..];
+            self.output_len += written;
+            self.ends_len += ended;
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return records,
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => records.push(self.take_record()),
+            }
+        }
+    }
+    fn take_record(&mut self) -> CsvRecord {
+        let mut fields = Vec::with_capacity(self.ends_len);
+        let mut start = 0;
+        for &end in &self.ends[..self.ends_len] {
+            fields.push(String::from_utf8(self.output[start..end].to_vec()));
+            start = end;
+        }
+        self.output_len = 0;
+        self.ends_len = 0;
+        fields.into_iter().collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CsvColumns {
+    email: usize,
+    name: usize,
+}
+
+impl CsvColumns {
+    fn from_header(header: &[String]) -> Result<Self, SubscriberImportError> {
+        let position = |column: &str| {
+            header
+                .iter()
+                // Spreadsheet exports may start with a byte order mark
+                .map(|field| field.trim_start_matches('\u{feff}').trim())
+                .position(|field| field.eq_ignore_ascii_case(column))
+                .ok_or_else(|| {
+                    SubscriberImportError::InvalidCsv(format!(
+                        "Header row lacks a '{}' column.",
+                        column
+                    ))
+                })
+        };
+        Ok(Self {
+            email: position("email")?,
+            name: position("name")?,
+        })
+    }
+    fn validate(
+        &self,
+        fields: &[String],
+    ) -> Result<(SubscriptionFilteredEmail, SubscriptionFilteredName), String> {
+        let field = |index: usize, column: &str| {
+            fields
+                .get(index)
+                .ok_or_else(|| format!("Row lacks a value for the '{}' column.", column))
+        };
+        let email = SubscriptionFilteredEmail::parse(field(self.email, "email")?)?;
+        let name = SubscriptionFilteredName::parse(field(self.name, "name")?)?;
+        Ok((email, name))
+    }
+}
+
+/// Import subscribers from a CSV upload with `email` and `name` header columns in one transaction.
+/// Rows passing the subscription validators are streamed with `COPY` into a staging table, then
+/// merged skipping emails already subscribed. Imported subscribers are confirmed, as they opted in
+/// with the previous provider.
+#[tracing::instrument(
+    name = "Importing subscribers from CSV.",
+    skip(postgres_client, upload)
+)]
+pub async fn import_subscribers<S, B, E>(
+    postgres_client: &mut Object,
+    upload: S,
+) -> Result<ImportReport, SubscriberImportError>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+    E: fmt::Display,
+{
+    let transaction = postgres_client.transaction().await?;
+    transaction
+        .batch_execute(&format!(
+            "SET LOCAL idle_in_transaction_session_timeout = '{}s'",
+            IMPORT_STALL_TIMEOUT.as_secs()
+        ))
+        .await?;
+    // Statements on the temporary table are not cached, it is recreated by every import
+    transaction
+        .batch_execute(
+            r#"
+                CREATE TEMPORARY TABLE subscription_import(
+                    csv_row BIGINT NOT NULL,
+                    id UUID NOT NULL,
+                    email TEXT NOT NULL,
+                    name TEXT NOT NULL
+                ) ON COMMIT DROP
+            "#,
+        )
+        .await?;
+    let sink = transaction
+        .copy_in("COPY subscription_import (csv_row, id, email, name) FROM STDIN BINARY")
+        .await?;
+    let writer = BinaryCopyInWriter::new(sink, &[Type::INT8, Type::UUID, Type::TEXT, Type::TEXT]);
+    pin_mut!(writer);
+    pin_mut!(upload);
+    let mut parser = CsvRecords::new();
+    let mut columns: Option<CsvColumns> = None;
+    let mut row: u64 = 0;
+    let mut rejected = Vec::new();
+    let mut end_of_upload = false;
+    while !end_of_upload {
+        let chunk = tokio::time::timeout(IMPORT_STALL_TIMEOUT, upload.next())
+            .await
+            .map_err(|_| {
+                SubscriberImportError::Upload(format!(
+                    "No data received for {} seconds.",
+                    IMPORT_STALL_TIMEOUT.as_secs()
+                ))
+            })?;
+        let records = match chunk {
+            Some(chunk) => {
+                let chunk =
+                    chunk.map_err(|error| SubscriberImportError::Upload(error.to_string()))?;
+                if chunk.as_ref().is_empty() {
+                    continue;
+                }
+                parser.feed(chunk.as_ref())
+            }
+            None => {
+                end_of_upload = true;
+                parser.feed(&[])
+            }
+        };
+        for record in records {
+            row += 1;
+            let (email, name) = match (columns, record) {
+                (Some(csv_columns), Ok(fields)) => match csv_columns.validate(&fields) {
+                    Ok(valid) => valid,
+                    Err(reason) => {
+                        rejected.push(ImportRejection {
+                            row,
+                            email: fields.get(csv_columns.email).cloned().unwrap_or_default(),
+                            reason,
+                        });
+                        continue;
+                    }
+                },
+                (Some(_), Err(_)) => {
+                    rejected.push(ImportRejection {
+                        row,
+                        email: String::new(),
+                        reason: "Row is not valid UTF-8.".to_owned(),
+                    });
+                    continue;
+                }
+                (None, Ok(header)) => {
+                    columns = Some(CsvColumns::from_header(&header)?);
+                    continue;
+                }
+                (None, Err(_)) => {
+                    return Err(SubscriberImportError::InvalidCsv(
+                        "Header row is not valid UTF-8.".to_owned(),
+                    ))
+                }
+            };
+            let csv_row = row as i64;
+            let id = Uuid::new_v7(Timestamp::now(NoContext));
+            writer
+                .as_mut()
+                .write(&[&csv_row, &id, &email.as_str(), &name.as_ref()])
+                .await?;
+        }
+    }
+    if columns.is_none() {
+        return Err(SubscriberImportError::InvalidCsv(
+            "Upload is empty, expected a header row with 'email' and 'name' columns.".to_owned(),
+        ));
+    }
+    let copied = writer.finish().await?;
+    let skipped = transaction
+        .query(
+            r#"
+                WITH candidates AS (
+                    -- Emails are case-insensitive citext in the subscription table
+                    SELECT DISTINCT ON (lower(email)) id, email, name
+                    FROM subscription_import ORDER BY lower(email), csv_row
+                ), inserted AS (
+                    INSERT INTO newsletter.subscription (id, email, name, status)
+                    SELECT id, email, name, 'confirmed' FROM candidates
+                    ON CONFLICT (email) DO NOTHING
+                    RETURNING id
+                )
+                SELECT csv_row, email, id IN (SELECT id FROM candidates) AS already_subscribed
+                FROM subscription_import
+                WHERE id NOT IN (SELECT id FROM inserted)
+            "#,
+            &[],
+        )
+        .await?;
+    for skipped_row in &skipped {
+        let csv_row: i64 = skipped_row.get("csv_row");
+        let email: String = skipped_row.get("email");
+        let already_subscribed: bool = skipped_row.get("already_subscribed");
+        let reason = if already_subscribed {
+            format!("Email '{}' is already subscribed.", email)
+        } else {
+            format!("Email '{}' appears on an earlier row of the upload.", email)
+        };
+        rejected.push(ImportRejection {
+            row: csv_row as u64,
+            email,
+            reason,
+        });
+    }
+    transaction.commit().await?;
+    rejected.sort_by_key(|rejection| rejection.row);
+    Ok(ImportReport {
+        imported: copied - skipped.len() as u64,
+        rejected,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::subscription::subscriber_import::CsvRecords;
+
+    fn records(chunks: &[&str]) -> Vec<Vec<String>> {
+        let mut parser = CsvRecords::new();
+        let mut records = Vec::new();
+        for chunk in chunks.iter().chain(std::iter::once(&"")) {
+            records.extend(
+                parser
+                    .feed(chunk.as_bytes())
+                    .into_iter()
+                    .map(Result::unwrap),
+            );
+        }
+        records
+    }
+
+    #[test]
+    fn records_split_across_chunks_are_reassembled() {
+        assert_eq!(
+            vec![
+                vec!["email", "name"],
+                vec!["jane@drconopoima.com", "Jane Doe"],
+            ],
+            records(&["email,na", "me\njane@drcon", "opoima.com,Jane", " Doe"])
+        );
+    }
+
+    #[test]
+    fn quoted_fields_keep_separators_and_newlines() {
+        assert_eq!(
+            vec![vec!["a,b", "line\nbreak", "say \"hi\""]],
+            records(&["\"a,b\",\"line\nbreak\",\"say \"\"hi\"\"\"\n"])
+        );
+    }
+
+    #[test]
+    fn large_records_grow_buffers() {
+        let long_name = "x".repeat(5000);
+        let fields: Vec<String> = (0..40).map(|_| long_name.clone()).collect();
+        assert_eq!(vec![fields.clone()], records(&[&fields.join(",")]));
+    }
+}
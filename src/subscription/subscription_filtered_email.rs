@@ -4,7 +4,7 @@ use std::fmt;
 use std::ops::Deref;
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SubscriptionFilteredEmail(String);
 
 impl SubscriptionFilteredEmail {
@@ -95,7 +95,7 @@ mod tests {
     fn rejects_missing_at_symbol() {
         let tests = vec!["email.drconopoima.com", "[::1].127.0.0.1"];
         for input in tests {
-            assert_err!(SubscriptionFilteredEmail::parse(&input));
+            assert_err!(SubscriptionFilteredEmail::parse(input));
         }
     }
 
@@ -119,7 +119,7 @@ mod tests {
     fn rejects_missing_subject_address() {
         let tests = vec!["@drconopoima.com", "@127.0.0.1"];
         for input in tests {
-            assert_err!(SubscriptionFilteredEmail::from_str(&input));
+            assert_err!(SubscriptionFilteredEmail::from_str(input));
         }
     }
 
@@ -132,7 +132,7 @@ mod tests {
             "email@localhost",
         ];
         for input in tests {
-            assert_ok!(SubscriptionFilteredEmail::new(&input));
+            assert_ok!(SubscriptionFilteredEmail::new(input));
         }
     }
 
@@ -144,7 +144,7 @@ mod tests {
             "\nsomescript@unintended.input\t \n",
         ];
         for input in tests {
-            assert_ok!(SubscriptionFilteredEmail::parse(&input));
+            assert_ok!(SubscriptionFilteredEmail::parse(input));
         }
     }
 
@@ -160,11 +160,11 @@ mod tests {
             .map(|input| {
                 let method = methods_weights[sampling_methods.sample(&mut rng)].0;
                 if method.eq("new") {
-                    SubscriptionFilteredEmail::new(&input)
+                    SubscriptionFilteredEmail::new(input)
                 } else if method.eq("from_str") {
-                    SubscriptionFilteredEmail::from_str(&input)
+                    SubscriptionFilteredEmail::from_str(input)
                 } else {
-                    SubscriptionFilteredEmail::parse(&input)
+                    SubscriptionFilteredEmail::parse(input)
                 }
             })
             .collect();
@@ -177,7 +177,7 @@ mod tests {
     fn rejects_missing_tld() {
         let tests = vec!["abc", "abc@"];
         for input in tests {
-            assert_err!(SubscriptionFilteredEmail::new(&input));
+            assert_err!(SubscriptionFilteredEmail::new(input));
         }
     }
 
@@ -185,21 +185,21 @@ mod tests {
     fn rejects_intermediate_whitespace() {
         let tests = vec!["a @x.yz", "a\n@b.net"];
         for input in tests {
-            assert_err!(SubscriptionFilteredEmail::from_str(&input));
+            assert_err!(SubscriptionFilteredEmail::from_str(input));
         }
     }
 
     #[test]
     fn accepts_domain_label_63_characters() {
         let mut long_tld = "admin@local.".to_owned();
-        long_tld.extend("y".repeat(63).chars());
+        long_tld.push_str(&"y".repeat(63));
         let mut long_domain_label = "email@".to_owned();
-        long_domain_label.extend("y".repeat(63).chars());
+        long_domain_label.push_str(&"y".repeat(63));
         let long_domain = format!("{}.com", long_domain_label);
         let mut long_sub_domain_label = "anonymous@".to_owned();
-        long_sub_domain_label.extend("x".repeat(63).chars());
-        long_sub_domain_label.extend(".".repeat(1).chars());
-        long_sub_domain_label.extend("z".repeat(63).chars());
+        long_sub_domain_label.push_str(&"x".repeat(63));
+        long_sub_domain_label.push('.');
+        long_sub_domain_label.push_str(&"z".repeat(63));
         let long_sub_domain = format!("{}.net", long_sub_domain_label);
         let tests = vec![long_tld, long_domain, long_sub_domain];
         for input in tests {
@@ -210,16 +210,16 @@ mod tests {
     #[test]
     fn rejects_domain_label_64_characters() {
         let mut long_tld = "admin@abc.".to_owned();
-        long_tld.extend("n".repeat(64).chars());
+        long_tld.push_str(&"n".repeat(64));
         let mut long_domain_label = "email@".to_owned();
-        long_domain_label.extend("y".repeat(64).chars());
+        long_domain_label.push_str(&"y".repeat(64));
         let long_domain = format!("{}.com", long_domain_label);
         let mut long_sub_domain_label = "anonymous@".to_owned();
-        long_sub_domain_label.extend("x".repeat(63).chars());
-        long_sub_domain_label.extend(".".repeat(1).chars());
-        long_sub_domain_label.extend("y".repeat(64).chars());
-        long_sub_domain_label.extend(".".repeat(1).chars());
-        long_sub_domain_label.extend("z".repeat(63).chars());
+        long_sub_domain_label.push_str(&"x".repeat(63));
+        long_sub_domain_label.push('.');
+        long_sub_domain_label.push_str(&"y".repeat(64));
+        long_sub_domain_label.push('.');
+        long_sub_domain_label.push_str(&"z".repeat(63));
         let long_sub_domain = format!("{}.net", long_sub_domain_label);
         let tests = vec![long_tld, long_domain, long_sub_domain];
         for input in tests {
@@ -270,11 +270,11 @@ mod tests {
             let method = methods_weights[sampling_methods.sample(&mut rng)].0;
             let result: Result<SubscriptionFilteredEmail, String> = {
                 if method.eq("new") {
-                    SubscriptionFilteredEmail::new(&input)
+                    SubscriptionFilteredEmail::new(input)
                 } else if method.eq("from_str") {
-                    SubscriptionFilteredEmail::from_str(&input)
+                    SubscriptionFilteredEmail::from_str(input)
                 } else {
-                    SubscriptionFilteredEmail::parse(&input)
+                    SubscriptionFilteredEmail::parse(input)
                 }
             };
             if expected {
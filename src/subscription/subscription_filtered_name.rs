@@ -4,7 +4,7 @@ use std::fmt;
 use std::ops::Deref;
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SubscriptionFilteredName(String);
 
 impl SubscriptionFilteredName {
@@ -155,7 +155,7 @@ mod tests {
             "SSSniperWolf",
         ];
         for input in tests {
-            assert_ok!(SubscriptionFilteredName::from_str(&input));
+            assert_ok!(SubscriptionFilteredName::from_str(input));
         }
     }
 
@@ -173,7 +173,7 @@ mod tests {
             "Gordon Freeman, MSc;MBA;PhD,PMP®",
         ];
         for input in tests {
-            assert_ok!(SubscriptionFilteredName::new(&input));
+            assert_ok!(SubscriptionFilteredName::new(input));
         }
     }
 
@@ -186,7 +186,7 @@ mod tests {
             "Missing titles, MSc;;PhD,®",
         ];
         for input in tests {
-            assert_err!(SubscriptionFilteredName::from_str(&input));
+            assert_err!(SubscriptionFilteredName::from_str(input));
         }
     }
 
@@ -198,7 +198,7 @@ mod tests {
             "\nRyan Sees Through Copper\t \n",
         ];
         for input in tests {
-            assert_ok!(SubscriptionFilteredName::new(&input));
+            assert_ok!(SubscriptionFilteredName::new(input));
         }
     }
 
@@ -210,7 +210,7 @@ mod tests {
             "Rust[1]ndexLik{3}TheFirst(0)ne",
         ];
         for input in tests {
-            assert_err!(SubscriptionFilteredName::parse(&input));
+            assert_err!(SubscriptionFilteredName::parse(input));
         }
     }
 
@@ -223,7 +223,7 @@ mod tests {
             jumps \t \t\n    around   a lot",
         ];
         for input in tests {
-            assert_ok!(SubscriptionFilteredName::from_str(&input));
+            assert_ok!(SubscriptionFilteredName::from_str(input));
         }
     }
 
@@ -251,11 +251,11 @@ mod tests {
             .map(|input| {
                 let method = methods_weights[sampling_methods.sample(&mut rng)].0;
                 if method.eq("new") {
-                    SubscriptionFilteredName::new(&input)
+                    SubscriptionFilteredName::new(input)
                 } else if method.eq("from_str") {
-                    SubscriptionFilteredName::from_str(&input)
+                    SubscriptionFilteredName::from_str(input)
                 } else {
-                    SubscriptionFilteredName::parse(&input)
+                    SubscriptionFilteredName::parse(input)
                 }
             })
             .collect();
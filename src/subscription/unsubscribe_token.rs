@@ -0,0 +1,147 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+use std::convert::AsRef;
+use std::fmt;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Domain separation, so that signatures can't be reused for other kinds of links
+static UNSUBSCRIBE_TOKEN_PURPOSE: &[u8] = b"newsletter-unsubscribe:";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum UnsubscribeTokenError {
+    Malformed,
+    InvalidSignature,
+}
+
+impl fmt::Display for UnsubscribeTokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnsubscribeTokenError::Malformed => write!(f, "Malformed unsubscribe token."),
+            UnsubscribeTokenError::InvalidSignature => {
+                write!(f, "Invalid unsubscribe token signature.")
+            }
+        }
+    }
+}
+
+/// Stateless unsubscribe token, `<subscriber id>.<hex HMAC-SHA256 of the subscriber id>`.
+#[derive(Clone, Debug)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn sign(subscriber_id: Uuid, hmac_secret: &SecretString) -> Self {
+        let mut mac = new_mac(hmac_secret);
+        mac.update(subscriber_id.as_bytes());
+        let signature = hex::encode(mac.finalize().into_bytes());
+        Self(format!("{}.{}", subscriber_id.simple(), signature))
+    }
+    /// Return the subscriber id the token was signed for, checking the signature in constant time.
+    pub fn verify(token: &str, hmac_secret: &SecretString) -> Result<Uuid, UnsubscribeTokenError> {
+        let (subscriber_id, signature) = token
+            .split_once('.')
+            .ok_or(UnsubscribeTokenError::Malformed)?;
+        // Only the simple form that sign() produces, not the hyphenated, braced or URN ones
+        if subscriber_id.len() != 32 {
+            return Err(UnsubscribeTokenError::Malformed);
+        }
+        let subscriber_id =
+            Uuid::try_parse(subscriber_id).map_err(|_| UnsubscribeTokenError::Malformed)?;
+        let signature = hex::decode(signature).map_err(|_| UnsubscribeTokenError::Malformed)?;
+        let mut mac = new_mac(hmac_secret);
+        mac.update(subscriber_id.as_bytes());
+        mac.verify_slice(&signature)
+            .map_err(|_| UnsubscribeTokenError::InvalidSignature)?;
+        Ok(subscriber_id)
+    }
+    pub fn link(&self, base_url: &str) -> String {
+        format!(
+            "{}/subscription/unsubscribe?token={}",
+            base_url.trim_end_matches('/'),
+            self.0
+        )
+    }
+}
+
+fn new_mac(hmac_secret: &SecretString) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(hmac_secret.expose_secret().as_bytes())
+        .expect("HMAC can take key of any size");
+    mac.update(UNSUBSCRIBE_TOKEN_PURPOSE);
+    mac
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for UnsubscribeToken {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::subscription::{UnsubscribeToken, UnsubscribeTokenError};
+    use secrecy::SecretString;
+    use uuid::Uuid;
+
+    fn secret(value: &str) -> SecretString {
+        SecretString::from(value.to_owned())
+    }
+
+    #[test]
+    fn signed_token_is_verified() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::sign(subscriber_id, &secret("hmac-secret"));
+        assert_eq!(
+            Ok(subscriber_id),
+            UnsubscribeToken::verify(token.as_ref(), &secret("hmac-secret"))
+        );
+    }
+
+    #[test]
+    fn token_signed_with_other_secret_is_rejected() {
+        let token = UnsubscribeToken::sign(Uuid::new_v4(), &secret("other-secret"));
+        assert_eq!(
+            Err(UnsubscribeTokenError::InvalidSignature),
+            UnsubscribeToken::verify(token.as_ref(), &secret("hmac-secret"))
+        );
+    }
+
+    #[test]
+    fn token_for_tampered_subscriber_id_is_rejected() {
+        let token = UnsubscribeToken::sign(Uuid::new_v4(), &secret("hmac-secret"));
+        let (_, signature) = token.as_ref().split_once('.').unwrap();
+        let tampered = format!("{}.{}", Uuid::new_v4().simple(), signature);
+        assert_eq!(
+            Err(UnsubscribeTokenError::InvalidSignature),
+            UnsubscribeToken::verify(&tampered, &secret("hmac-secret"))
+        );
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        for token in [
+            "",
+            "no-separator",
+            "not-a-uuid.00",
+            "00000000000000000000000000000000.zz",
+            "00000000-0000-0000-0000-000000000000.00",
+            "{00000000-0000-0000-0000-000000000000}.00",
+            "urn:uuid:00000000-0000-0000-0000-000000000000.00",
+        ] {
+            assert_eq!(
+                Err(UnsubscribeTokenError::Malformed),
+                UnsubscribeToken::verify(token, &secret("hmac-secret")),
+                "Token '{}' should be malformed",
+                token
+            );
+        }
+    }
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use common::{
+    launch_http_server_with_admin, login_admin, session_cookie_value, ServerPostgres,
+    TEST_ADMIN_PASSWORD, TEST_ADMIN_USERNAME,
+};
+use newsletter_rs::authentication::{bootstrap_admin_user, SESSION_COOKIE_NAME};
+use newsletter_rs::configuration::AdminBootstrapSettings;
+use secrecy::SecretString;
+
+fn admin_route(server_postgres: &ServerPostgres, route: &str) -> String {
+    format!(
+        "{}{}",
+        server_postgres.admin_address.as_ref().unwrap(),
+        route
+    )
+}
+
+async fn post_login(
+    server_postgres: &ServerPostgres,
+    username: &str,
+    password: &str,
+) -> reqwest::Response {
+    let login_route = admin_route(server_postgres, "/admin/login");
+    reqwest::Client::new()
+        .post(&login_route)
+        .json(&serde_json::json!({
+            "username": username,
+            "password": password,
+        }))
+        .send()
+        .await
+        .unwrap_or_else(|_| panic!("Failed POST request to {}", login_route))
+}
+
+#[tokio::test]
+async fn login_sets_http_only_session_cookie() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    // Act
+    let response = post_login(&server_postgres, TEST_ADMIN_USERNAME, TEST_ADMIN_PASSWORD).await;
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let set_cookie = response
+        .headers()
+        .get(reqwest::header::SET_COOKIE)
+        .expect("No session cookie was set by login")
+        .to_str()
+        .unwrap();
+    assert!(set_cookie.starts_with(&format!("{}=", SESSION_COOKIE_NAME)));
+    assert!(set_cookie.contains("HttpOnly"));
+    assert!(set_cookie.contains("SameSite=Strict"));
+}
+
+#[tokio::test]
+async fn login_401_invalid_credentials() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let test_cases = vec![
+        (TEST_ADMIN_USERNAME, "wrong-password", "wrong password"),
+        ("unknown-admin", TEST_ADMIN_PASSWORD, "unknown username"),
+    ];
+    for (username, password, description) in test_cases {
+        // Act
+        let response = post_login(&server_postgres, username, password).await;
+        // Assert
+        assert_eq!(
+            401,
+            response.status().as_u16(),
+            "Expected login to be rejected with {}.",
+            description
+        );
+        assert_eq!(None, session_cookie_value(&response));
+    }
+}
+
+#[tokio::test]
+async fn admin_routes_401_without_valid_session() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let newsletters_route = admin_route(&server_postgres, "/admin/newsletters");
+    let progress_route = admin_route(
+        &server_postgres,
+        &format!("/admin/newsletters/{}", uuid::Uuid::new_v4()),
+    );
+    let cookies = vec![
+        (None, "missing session cookie"),
+        (Some("forged.00"), "forged session cookie"),
+    ];
+    for (cookie, description) in cookies {
+        let client = reqwest::Client::new();
+        let mut publish = client.post(&newsletters_route).json(&serde_json::json!({
+            "title": "Title",
+            "html_content": "<p>body</p>",
+            "text_content": "body",
+        }));
+        let mut progress = client.get(&progress_route);
+        if let Some(cookie) = cookie {
+            let cookie = format!("{}={}", SESSION_COOKIE_NAME, cookie);
+            publish = publish.header(reqwest::header::COOKIE, &cookie);
+            progress = progress.header(reqwest::header::COOKIE, &cookie);
+        }
+        // Act
+        let publish = publish.send().await.unwrap();
+        let progress = progress.send().await.unwrap();
+        // Assert
+        assert_eq!(
+            401,
+            publish.status().as_u16(),
+            "Publish with {}.",
+            description
+        );
+        assert_eq!(
+            401,
+            progress.status().as_u16(),
+            "Progress with {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn healthcheck_on_admin_server_does_not_require_session() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    // Act
+    let response = reqwest::get(admin_route(&server_postgres, "/healthcheck"))
+        .await
+        .expect("Failed GET request to admin healthcheck");
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn logout_invalidates_session() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let session_cookie = login_admin(
+        server_postgres.admin_address.as_ref().unwrap(),
+        TEST_ADMIN_PASSWORD,
+    )
+    .await;
+    let cookie = format!("{}={}", SESSION_COOKIE_NAME, session_cookie);
+    let progress_route = admin_route(
+        &server_postgres,
+        &format!("/admin/newsletters/{}", uuid::Uuid::new_v4()),
+    );
+    let client = reqwest::Client::new();
+    // Act
+    let response = client
+        .post(admin_route(&server_postgres, "/admin/logout"))
+        .header(reqwest::header::COOKIE, &cookie)
+        .send()
+        .await
+        .expect("Failed POST request to logout");
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(Some(String::new()), session_cookie_value(&response));
+    let response = client
+        .get(&progress_route)
+        .header(reqwest::header::COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+    // Sessions of other logins remain valid
+    let response = server_postgres
+        .admin_client
+        .get(&progress_route)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn bootstrap_admin_is_only_created_while_no_user_exists() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let bootstrap = AdminBootstrapSettings {
+        username: "second-admin".to_owned(),
+        password: SecretString::from("second-admin-password".to_owned()),
+    };
+    // Act
+    let created = bootstrap_admin_user(&server_postgres.postgres_pool, &bootstrap)
+        .await
+        .expect("Failed to bootstrap admin user");
+    // Assert
+    assert!(!created);
+    let response = post_login(&server_postgres, "second-admin", "second-admin-password").await;
+    assert_eq!(401, response.status().as_u16());
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use common::{launch_http_server_with_admin, ServerPostgres};
+
+fn admin_route(server_postgres: &ServerPostgres, route: &str) -> String {
+    format!(
+        "{}{}",
+        server_postgres.admin_address.as_ref().unwrap(),
+        route
+    )
+}
+
+// Create an API key through the admin session, returning its id and the key itself
+async fn create_api_key(server_postgres: &ServerPostgres, scopes: &[&str]) -> (String, String) {
+    let response = server_postgres
+        .admin_client
+        .post(admin_route(server_postgres, "/admin/api-keys"))
+        .json(&serde_json::json!({"name": "ci", "scopes": scopes}))
+        .send()
+        .await
+        .expect("Failed POST request to create API key");
+    assert_eq!(201, response.status().as_u16());
+    let created: serde_json::Value = response.json().await.unwrap();
+    (
+        created["key_id"].as_str().unwrap().to_owned(),
+        created["api_key"].as_str().unwrap().to_owned(),
+    )
+}
+
+async fn publish_with_bearer(server_postgres: &ServerPostgres, api_key: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(admin_route(server_postgres, "/admin/newsletters"))
+        .bearer_auth(api_key)
+        .json(&serde_json::json!({
+            "title": "Published from CI",
+            "html_content": "<p>body</p>",
+            "text_content": "body",
+        }))
+        .send()
+        .await
+        .expect("Failed POST request to publish newsletter")
+}
+
+#[tokio::test]
+async fn api_key_with_scope_publishes_newsletter_and_tracks_usage() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let (key_id, api_key) = create_api_key(&server_postgres, &["newsletters:send"]).await;
+    assert!(api_key.starts_with("nl_"));
+    // Act
+    let response = publish_with_bearer(&server_postgres, &api_key).await;
+    // Assert
+    assert_eq!(202, response.status().as_u16());
+    let listing: serde_json::Value = server_postgres
+        .admin_client
+        .get(admin_route(&server_postgres, "/admin/api-keys"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let listed = &listing.as_array().unwrap()[0];
+    assert_eq!(key_id, listed["key_id"]);
+    assert!(listed["last_used_at"].is_string());
+    assert!(listed.get("api_key").is_none());
+    assert!(api_key.starts_with(listed["key_prefix"].as_str().unwrap()));
+}
+
+#[tokio::test]
+async fn api_key_without_scope_is_forbidden() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let (_, api_key) = create_api_key(&server_postgres, &["subscribers:read"]).await;
+    // Act
+    let response = publish_with_bearer(&server_postgres, &api_key).await;
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn revoked_and_unknown_api_keys_are_rejected() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let (key_id, api_key) = create_api_key(&server_postgres, &["newsletters:send"]).await;
+    let response = server_postgres
+        .admin_client
+        .delete(admin_route(
+            &server_postgres,
+            &format!("/admin/api-keys/{}", key_id),
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(204, response.status().as_u16());
+    // Act
+    let revoked = publish_with_bearer(&server_postgres, &api_key).await;
+    let unknown = publish_with_bearer(&server_postgres, "nl_unknown").await;
+    // Assert
+    assert_eq!(401, revoked.status().as_u16());
+    assert_eq!(401, unknown.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_keys_cannot_manage_api_keys() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let (_, api_key) = create_api_key(
+        &server_postgres,
+        &["subscribers:read", "newsletters:read", "newsletters:send"],
+    )
+    .await;
+    // Act
+    let response = reqwest::Client::new()
+        .post(admin_route(&server_postgres, "/admin/api-keys"))
+        .bearer_auth(&api_key)
+        .json(&serde_json::json!({"name": "escalated", "scopes": ["newsletters:send"]}))
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn create_api_key_400_invalid_input() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({"name": "ci", "scopes": ["subscribers:write"]}),
+            "unknown scope",
+        ),
+        (serde_json::json!({"name": "ci", "scopes": []}), "no scopes"),
+        (
+            serde_json::json!({"name": " ", "scopes": ["newsletters:send"]}),
+            "blank name",
+        ),
+    ];
+    for (invalid_body, description) in test_cases {
+        // Act
+        let response = server_postgres
+            .admin_client
+            .post(admin_route(&server_postgres, "/admin/api-keys"))
+            .json(&invalid_body)
+            .send()
+            .await
+            .unwrap();
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "Expected 400 Bad Request with {}.",
+            description
+        );
+    }
+}
@@ -0,0 +1,344 @@
+// Shared helpers for integration tests, not every test binary uses all of them
+#![allow(dead_code)]
+
+use actix_web::dev::Server;
+use anyhow::anyhow;
+use anyhow::Error;
+use async_trait::async_trait;
+use deadpool_postgres::Pool;
+use newsletter_rs::{
+    authentication::{bootstrap_admin_user, SESSION_COOKIE_NAME},
+    configuration::{
+        get_configuration, AdminBootstrapSettings, DeliveryWorkerSettings, DriftPolicy,
+        MigrationSettings, SslSettings,
+    },
+    email_client::{EmailClient, EmailMessage},
+    issue_delivery_worker::{run_worker_until_stopped, UnsubscribeLinkSettings},
+    postgres::{generate_connection_pool, get_client, migrate_database, run_simple_query},
+    telemetry::{get_subscriber, init_subscriber},
+};
+use secrecy::SecretString;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::{
+    io::{sink, stdout},
+    time,
+};
+use uuid::Uuid;
+
+static TRACING_LAUNCH_LOCK: OnceLock<Mutex<bool>> = OnceLock::new();
+static TRACING_IS_INITIALIZED: OnceLock<bool> = OnceLock::new();
+
+pub struct ServerPostgres {
+    pub address: String,
+    pub admin_address: Option<String>,
+    pub postgres_pool: Pool,
+    pub email_client: Arc<RecordingEmailClient>,
+    pub hmac_secret: SecretString,
+    // Sends the session cookie of the bootstrap admin, logged in at launch
+    pub admin_client: reqwest::Client,
+}
+
+pub static TEST_ADMIN_USERNAME: &str = "admin";
+pub static TEST_ADMIN_PASSWORD: &str = "test-admin-password";
+
+// Email backend keeping every sent message in memory for assertions
+#[derive(Default)]
+pub struct RecordingEmailClient {
+    pub sent: Mutex<Vec<EmailMessage>>,
+    // Recipients whose deliveries are rejected
+    pub failing_recipients: Mutex<Vec<String>>,
+}
+
+#[async_trait]
+impl EmailClient for RecordingEmailClient {
+    async fn send_email(&self, message: &EmailMessage) -> Result<(), Error> {
+        if self
+            .failing_recipients
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|recipient| recipient == message.to.as_str())
+        {
+            return Err(anyhow!("Recipient '{}' was rejected", message.to));
+        }
+        self.sent.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+}
+
+// Quick polling and retries so that delivery tests complete fast
+pub fn test_delivery_worker_settings() -> DeliveryWorkerSettings {
+    DeliveryWorkerSettings {
+        enabled: true,
+        pollintervalms: 50,
+        maxretries: 3,
+        initialbackoffms: 10,
+        maxbackoffms: 40,
+    }
+}
+
+impl RecordingEmailClient {
+    // Extract the confirmation link out of the last sent plain-text email body
+    pub fn last_confirmation_link(&self) -> String {
+        let sent = self.sent.lock().unwrap();
+        let message = sent.last().expect("No email was sent");
+        message
+            .text_body
+            .split_whitespace()
+            .find(|word| word.contains("/subscription/confirm?token="))
+            .expect("No confirmation link in email body")
+            .to_owned()
+    }
+    // Signed unsubscribe link carried by the last sent email
+    pub fn last_unsubscribe_link(&self) -> String {
+        let sent = self.sent.lock().unwrap();
+        sent.last()
+            .expect("No email was sent")
+            .unsubscribe_link
+            .to_owned()
+    }
+}
+
+// Launch an instance for our HTTP server in the background
+pub async fn launch_http_server() -> ServerPostgres {
+    launch_http_servers(false).await
+}
+
+// Launch the public HTTP server along with the admin server, each on a random port
+pub async fn launch_http_server_with_admin() -> ServerPostgres {
+    launch_http_servers(true).await
+}
+
+async fn launch_http_servers(with_admin: bool) -> ServerPostgres {
+    let tracing_launch_locked = TRACING_LAUNCH_LOCK
+        .get_or_init(|| Mutex::new(true))
+        .lock()
+        .unwrap();
+    if TRACING_IS_INITIALIZED.get().is_none() {
+        let filter_level = "debug".to_owned();
+        let subscriber_name = "test".to_owned();
+        if std::env::var("TEST_LOG").is_ok() {
+            let subscriber = get_subscriber(subscriber_name, filter_level, stdout);
+            init_subscriber(subscriber).expect("Failed to initializer subscriber to stdout");
+        } else {
+            let subscriber = get_subscriber(subscriber_name, filter_level, sink);
+            init_subscriber(subscriber).expect("Failed to initialize subscriber");
+        }
+        _ = TRACING_IS_INITIALIZED.set(true);
+    }
+    std::mem::drop(tracing_launch_locked);
+    let config_file: &str = "main.yaml";
+    let mut configuration = get_configuration(config_file).unwrap_or_else(|error| {
+        panic!(
+            "ERROR: Failed to read configuration file '{}': {}",
+            &config_file, error
+        )
+    });
+    let migration_settings = MigrationSettings {
+        migrate: true,
+        folder: None,
+        ondrift: DriftPolicy::Fail,
+    };
+    configuration.database.migration = Some(migration_settings);
+    let isolated_database_name = Uuid::new_v4().to_string();
+    let database_name = isolated_database_name.replace("-", "");
+    let pool = generate_connection_pool(
+        configuration.database.connection_without_database().build(),
+        &SslSettings::default(),
+        None,
+    )
+    .unwrap();
+    let postgres_client = get_client(pool).await.unwrap();
+    let _ = run_simple_query(
+        &postgres_client,
+        &format!("CREATE DATABASE \"{}\"", database_name),
+    )
+    .await;
+    configuration.database.database = Some(database_name.to_owned());
+    let hmac_secret = configuration.application.hmacsecret.clone();
+    let postgres_pool: Pool = migrate_database(configuration.database)
+        .await
+        .expect("Failed to migrate database");
+    let local_addr = "localhost";
+    let address: (&str, u16) = (local_addr, 0);
+    let listener = TcpListener::bind(address).expect("Failed to bind random port");
+    let port = listener.local_addr().unwrap().port();
+    let address = format!("http://{}:{}", local_addr, port);
+    let mut admin_listener = None;
+    let mut admin_address = None;
+    if with_admin {
+        let listener = TcpListener::bind((local_addr, 0)).expect("Failed to bind random port");
+        admin_address = Some(format!(
+            "http://{}:{}",
+            local_addr,
+            listener.local_addr().unwrap().port()
+        ));
+        admin_listener = Some(listener);
+    }
+    let email_client = Arc::new(RecordingEmailClient::default());
+    let (server, admin_server): (Server, Option<Server>) = newsletter_rs::startup::run(
+        listener,
+        postgres_pool.clone(),
+        None,
+        admin_listener,
+        Some(time::Duration::from_millis(100000000)),
+        email_client.clone(),
+        address.to_owned(),
+        hmac_secret.clone(),
+        time::Duration::from_secs(3600),
+    )
+    .expect("Failed to listen on address");
+    std::mem::drop(tokio::spawn(server));
+    std::mem::drop(tokio::spawn(run_worker_until_stopped(
+        Arc::new(postgres_pool.clone()),
+        email_client.clone(),
+        test_delivery_worker_settings(),
+        UnsubscribeLinkSettings {
+            base_url: address.to_owned(),
+            hmac_secret: hmac_secret.clone(),
+        },
+    )));
+    let mut admin_client = reqwest::Client::new();
+    if let Some(admin_server) = admin_server {
+        std::mem::drop(tokio::spawn(admin_server));
+        let bootstrap = AdminBootstrapSettings {
+            username: TEST_ADMIN_USERNAME.to_owned(),
+            password: SecretString::from(TEST_ADMIN_PASSWORD.to_owned()),
+        };
+        bootstrap_admin_user(&postgres_pool, &bootstrap)
+            .await
+            .expect("Failed to bootstrap admin user");
+        let session_cookie =
+            login_admin(admin_address.as_ref().unwrap(), TEST_ADMIN_PASSWORD).await;
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::COOKIE,
+            format!("{}={}", SESSION_COOKIE_NAME, session_cookie)
+                .parse()
+                .unwrap(),
+        );
+        admin_client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
+    }
+    ServerPostgres {
+        address,
+        admin_address,
+        postgres_pool,
+        email_client,
+        hmac_secret,
+        admin_client,
+    }
+}
+
+// Log in as the bootstrap admin, returning the value of the session cookie
+pub async fn login_admin(admin_address: &str, password: &str) -> String {
+    let login_route = format!("{}/admin/login", admin_address);
+    let response = reqwest::Client::new()
+        .post(&login_route)
+        .json(&serde_json::json!({
+            "username": TEST_ADMIN_USERNAME,
+            "password": password,
+        }))
+        .send()
+        .await
+        .unwrap_or_else(|_| panic!("Failed POST request to {}", login_route));
+    assert_eq!(200, response.status().as_u16());
+    session_cookie_value(&response).expect("No session cookie was set by login")
+}
+
+// Value of the session cookie set by a response, if any
+pub fn session_cookie_value(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .filter_map(|header| header.split(';').next())
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE_NAME)
+        .map(|(_, value)| value.to_owned())
+}
+
+#[derive(serde::Serialize)]
+pub struct Body {
+    pub email: String,
+    pub name: String,
+}
+
+pub async fn post_subscription(server_postgres: &ServerPostgres, body: &Body) -> reqwest::Response {
+    let body_encoded = serde_urlencoded::to_string(body).unwrap();
+    let subscriptions_route = &format!("{}/subscription", server_postgres.address);
+    reqwest::Client::new()
+        .post(subscriptions_route)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body_encoded)
+        .send()
+        .await
+        .unwrap_or_else(|_| panic!("Failed POST request to {}", subscriptions_route))
+}
+
+// Subscribe and follow the emailed confirmation link
+pub async fn create_confirmed_subscriber(server_postgres: &ServerPostgres, email: &str) {
+    let body = Body {
+        email: email.to_owned(),
+        name: "Jane Doe".to_owned(),
+    };
+    let response = post_subscription(server_postgres, &body).await;
+    assert_eq!(200, response.status().as_u16());
+    let confirmation_link = server_postgres.email_client.last_confirmation_link();
+    let response = reqwest::get(&confirmation_link)
+        .await
+        .unwrap_or_else(|_| panic!("Failed GET request to {}", confirmation_link));
+    assert_eq!(200, response.status().as_u16());
+}
+
+// Publish a newsletter issue through the admin server, returning its issue id
+pub async fn publish_newsletter(server_postgres: &ServerPostgres, title: &str) -> String {
+    let newsletters_route = format!(
+        "{}/admin/newsletters",
+        server_postgres.admin_address.as_ref().unwrap()
+    );
+    let response = server_postgres
+        .admin_client
+        .post(&newsletters_route)
+        .json(&serde_json::json!({
+            "title": title,
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+        }))
+        .send()
+        .await
+        .unwrap_or_else(|_| panic!("Failed POST request to {}", newsletters_route));
+    assert_eq!(202, response.status().as_u16());
+    let published: serde_json::Value = response.json().await.unwrap();
+    published["issue_id"].as_str().unwrap().to_owned()
+}
+
+// Poll delivery progress of an issue until the delivery worker completes it
+pub async fn wait_for_completed_issue(
+    server_postgres: &ServerPostgres,
+    issue_id: &str,
+) -> serde_json::Value {
+    let admin_address = server_postgres.admin_address.as_ref().unwrap();
+    let progress_route = format!("{}/admin/newsletters/{}", admin_address, issue_id);
+    for _ in 0..100 {
+        let response = server_postgres
+            .admin_client
+            .get(&progress_route)
+            .send()
+            .await
+            .unwrap_or_else(|_| panic!("Failed GET request to {}", progress_route));
+        assert_eq!(200, response.status().as_u16());
+        let progress: serde_json::Value = response.json().await.unwrap();
+        if progress["status"] == "completed" {
+            return progress;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("Newsletter issue '{}' was not completed in time", issue_id);
+}
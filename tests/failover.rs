@@ -0,0 +1,153 @@
+mod common;
+
+use common::{launch_http_server, post_subscription, Body};
+use newsletter_rs::configuration::{
+    get_configuration, DriftPolicy, MigrationSettings, PostgresHostSettings,
+};
+use newsletter_rs::postgres::{migrate_database, quote_identifier, PostgresRouter};
+use newsletter_rs::readiness::{probe_readiness, STATUS_FAIL, STATUS_WARN};
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Demoted nodes reject writes like databases defaulting to read-only transactions
+async fn set_database_read_only(postgres_client: &deadpool_postgres::Object, read_only: bool) {
+    let database: String = postgres_client
+        .query_one("SELECT current_database()", &[])
+        .await
+        .unwrap()
+        .get(0);
+    let setting = if read_only {
+        "SET default_transaction_read_only = on"
+    } else {
+        "RESET default_transaction_read_only"
+    };
+    postgres_client
+        .batch_execute(&format!(
+            "ALTER DATABASE {} {}",
+            quote_identifier(&database),
+            setting
+        ))
+        .await
+        .expect("Failed to alter database");
+    // Existing connections, even those in use like the delivery worker's, are replaced by new
+    // ones seeing the setting
+    postgres_client
+        .batch_execute(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity
+            WHERE datname = current_database() AND pid <> pg_backend_pid()",
+        )
+        .await
+        .expect("Failed to terminate connections");
+}
+
+#[tokio::test]
+async fn subscriptions_on_read_only_node_are_unavailable_until_reconnected() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    let admin_client = server_postgres.postgres_pool.get().await.unwrap();
+    set_database_read_only(&admin_client, true).await;
+    let body = Body {
+        email: "failover@drconopoima.com".to_owned(),
+        name: "Jane Doe".to_owned(),
+    };
+    // Act
+    let response = post_subscription(&server_postgres, &body).await;
+    // Assert
+    assert_eq!(503, response.status().as_u16());
+    assert!(response.headers().contains_key("retry-after"));
+    // Act
+    set_database_read_only(&admin_client, false).await;
+    let response = post_subscription(&server_postgres, &body).await;
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn readiness_probe_drains_pool_connected_to_read_only_node() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    let admin_client = server_postgres.postgres_pool.get().await.unwrap();
+    set_database_read_only(&admin_client, true).await;
+    let router = Arc::new(PostgresRouter::new(
+        server_postgres.postgres_pool.clone(),
+        None,
+    ));
+    // Act
+    let health = probe_readiness(router.clone()).await;
+    // Assert
+    assert_eq!(STATUS_WARN, health.status);
+    assert_eq!(STATUS_FAIL, health.checks.postgres_write.status);
+    assert!(health.checks.postgres_write.failover_detected_at.is_some());
+    assert!(router.failover_detected_at().is_some());
+}
+
+// Standbys report being in recovery, shadow pg_catalog's function to do the same
+async fn set_database_in_recovery(postgres_client: &deadpool_postgres::Object) {
+    let database: String = postgres_client
+        .query_one("SELECT current_database()", &[])
+        .await
+        .unwrap()
+        .get(0);
+    postgres_client
+        .batch_execute(&format!(
+            "CREATE FUNCTION public.pg_is_in_recovery() RETURNS boolean LANGUAGE sql AS 'SELECT true';
+            ALTER DATABASE {} SET search_path = \"$user\", public, pg_catalog;
+            SELECT pg_terminate_backend(pid) FROM pg_stat_activity
+            WHERE datname = current_database() AND pid <> pg_backend_pid()",
+            quote_identifier(&database)
+        ))
+        .await
+        .expect("Failed to shadow pg_is_in_recovery");
+}
+
+#[tokio::test]
+async fn readiness_probe_drains_pool_connected_to_node_in_recovery() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    let admin_client = server_postgres.postgres_pool.get().await.unwrap();
+    set_database_in_recovery(&admin_client).await;
+    // Only new sessions see the altered search_path
+    drop(deadpool_postgres::Object::take(admin_client));
+    let router = Arc::new(PostgresRouter::new(
+        server_postgres.postgres_pool.clone(),
+        None,
+    ));
+    // Act
+    let health = probe_readiness(router.clone()).await;
+    // Assert
+    assert_eq!(STATUS_WARN, health.status);
+    assert_eq!(STATUS_FAIL, health.checks.postgres_write.status);
+    assert_eq!(Some(true), health.checks.postgres_write.pg_is_in_recovery);
+    assert!(health.checks.postgres_write.failover_detected_at.is_some());
+    assert!(router.failover_detected_at().is_some());
+}
+
+#[tokio::test]
+async fn primary_connections_go_to_candidate_hosts_accepting_writes() {
+    // Arrange
+    let mut configuration = get_configuration("main.yaml").expect("Failed to read configuration");
+    configuration.database.database = Some(Uuid::new_v4().simple().to_string());
+    configuration.database.migration = Some(MigrationSettings {
+        migrate: true,
+        folder: None,
+        ondrift: DriftPolicy::Fail,
+    });
+    // Former primary is gone, the candidate was promoted
+    configuration.database.candidatehosts = Some(vec![PostgresHostSettings {
+        host: configuration.database.host.to_owned(),
+        port: configuration.database.port,
+    }]);
+    configuration.database.port = 1;
+    // Act
+    let pool = migrate_database(configuration.database)
+        .await
+        .expect("Failed to connect to candidate host");
+    // Assert
+    let postgres_client = pool.get().await.unwrap();
+    let read_only: String = postgres_client
+        .query_one("SHOW transaction_read_only", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!("off", read_only);
+}
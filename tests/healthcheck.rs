@@ -1,95 +1,6 @@
-use actix_web::dev::Server;
-use deadpool_postgres::Pool;
-use newsletter_rs::{
-    configuration::{get_configuration, MigrationSettings},
-    postgres::{generate_connection_pool, get_client, migrate_database, run_simple_query},
-    telemetry::{get_subscriber, init_subscriber},
-};
-use secrecy::SecretString;
-use std::net::TcpListener;
-use std::sync::Mutex;
-use std::sync::OnceLock;
-use std::{
-    io::{sink, stdout},
-    time,
-};
-use uuid::Uuid;
+mod common;
 
-static TRACING_LAUNCH_LOCK: OnceLock<Mutex<bool>> = OnceLock::new();
-static TRACING_IS_INITIALIZED: OnceLock<bool> = OnceLock::new();
-
-pub struct ServerPostgres {
-    pub address: String,
-    pub postgres_pool: Pool,
-}
-
-// Launch an instance for our HTTP server in the background
-async fn launch_http_server() -> ServerPostgres {
-    let tracing_launch_locked = TRACING_LAUNCH_LOCK
-        .get_or_init(|| Mutex::new(true))
-        .lock()
-        .unwrap();
-    if TRACING_IS_INITIALIZED.get().is_none() {
-        let filter_level = "debug".to_owned();
-        let subscriber_name = "test".to_owned();
-        if std::env::var("TEST_LOG").is_ok() {
-            let subscriber = get_subscriber(subscriber_name, filter_level, stdout);
-            init_subscriber(subscriber).expect("Failed to initializer subscriber to stdout");
-        } else {
-            let subscriber = get_subscriber(subscriber_name, filter_level, sink);
-            init_subscriber(subscriber).expect("Failed to initialize subscriber");
-        }
-        _ = TRACING_IS_INITIALIZED.set(true);
-    }
-    std::mem::drop(tracing_launch_locked);
-    let config_file: &str = "main.yaml";
-    let mut configuration = get_configuration(config_file).unwrap_or_else(|error| {
-        panic!(
-            "ERROR: Failed to read configuration file '{}': {}",
-            &config_file, error
-        )
-    });
-    let migration_settings = MigrationSettings {
-        migrate: true,
-        folder: "migrations".to_owned(),
-    };
-    configuration.database.migration = Some(migration_settings);
-    let isolated_database_name = Uuid::new_v4().to_string();
-    let database_name = isolated_database_name.replace("-", "");
-    let postgres_connection_string =
-        SecretString::from(configuration.database.connection_string_without_database());
-    let pool = generate_connection_pool(&postgres_connection_string, false, None).unwrap();
-    let postgres_client = get_client(pool).await.unwrap();
-    let _ = run_simple_query(
-        &postgres_client,
-        &format!("CREATE DATABASE \"{}\"", database_name),
-    )
-    .await;
-    configuration.database.database = Some(database_name.to_owned());
-    let postgres_pool: Pool = migrate_database(configuration.database).await;
-    let local_addr = "localhost";
-    let address: (&str, u16) = (local_addr, 0);
-    let listener = TcpListener::bind(address).expect("Failed to bind random port");
-    let port = listener.local_addr().unwrap().port();
-    let (server, _): (Server, _) = newsletter_rs::startup::run(
-        listener,
-        postgres_pool.clone(),
-        None,
-        Some(time::Duration::from_millis(100000000)),
-    )
-    .expect("Failed to listen on address");
-    let _ = tokio::spawn(server);
-    ServerPostgres {
-        address: format!("http://{}:{}", local_addr, port),
-        postgres_pool,
-    }
-}
-
-#[derive(serde::Serialize)]
-struct Body {
-    email: String,
-    name: String,
-}
+use common::{launch_http_server, post_subscription, Body};
 
 #[tokio::test]
 async fn healthcheck_endpoint() {
@@ -103,7 +14,7 @@ async fn healthcheck_endpoint() {
         .get(healthcheck_route)
         .send()
         .await
-        .expect(&format!("Failed GET request to {}", healthcheck_route));
+        .unwrap_or_else(|_| panic!("Failed GET request to {}", healthcheck_route));
     // Assert
     // Status 200 OK
     assert!(response.status().is_success());
@@ -129,7 +40,7 @@ async fn subscription_200_valid_form_data() {
         .body(body_encoded)
         .send()
         .await
-        .expect(&format!("Failed POST request to {}", subscriptions_route));
+        .unwrap_or_else(|_| panic!("Failed POST request to {}", subscriptions_route));
     // Assert
     assert_eq!(200, response.status().as_u16());
     // Act
@@ -147,8 +58,8 @@ async fn subscription_200_valid_form_data() {
         .await
         .expect("Failed to fetch saved subscription.");
     // Assert
-    let retrieved_email: &str = row_results[0].get(&"email");
-    let retrieved_name: &str = row_results[0].get(&"name");
+    let retrieved_email: &str = row_results[0].get("email");
+    let retrieved_name: &str = row_results[0].get("name");
     assert_eq!(&retrieved_email, &email_field);
     assert_eq!(&retrieved_name, &name_field);
 }
@@ -173,7 +84,7 @@ async fn subscription_400_incomplete_form_data() {
             .body(invalid_body)
             .send()
             .await
-            .expect(&format!("Failed POST request to {}", subscriptions_route));
+            .unwrap_or_else(|_| panic!("Failed POST request to {}", subscriptions_route));
         // Assert
         assert_eq!(
             400,
@@ -184,3 +95,86 @@ async fn subscription_400_incomplete_form_data() {
         )
     }
 }
+
+#[tokio::test]
+async fn subscription_sends_confirmation_email_and_stays_pending() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    let email_field = "pending_confirmation@drconopoima.com";
+    let body = Body {
+        email: email_field.to_owned(),
+        name: "Jane Doe".to_owned(),
+    };
+    // Act
+    let response = post_subscription(&server_postgres, &body).await;
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(1, server_postgres.email_client.sent.lock().unwrap().len());
+    let confirmation_link = server_postgres.email_client.last_confirmation_link();
+    assert!(confirmation_link.starts_with(&server_postgres.address));
+    let client = server_postgres.postgres_pool.get().await.unwrap();
+    let row = client
+        .query_one(
+            "SELECT status FROM newsletter.subscription WHERE email=$1::TEXT",
+            &[&email_field],
+        )
+        .await
+        .expect("Failed to fetch saved subscription.");
+    let status: &str = row.get("status");
+    assert_eq!("pending_confirmation", status);
+}
+
+#[tokio::test]
+async fn subscription_confirm_link_confirms_subscriber() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    let email_field = "confirm_me@drconopoima.com";
+    let body = Body {
+        email: email_field.to_owned(),
+        name: "Jane Doe".to_owned(),
+    };
+    let response = post_subscription(&server_postgres, &body).await;
+    assert_eq!(200, response.status().as_u16());
+    let confirmation_link = server_postgres.email_client.last_confirmation_link();
+    // Act
+    let response = reqwest::get(&confirmation_link)
+        .await
+        .unwrap_or_else(|_| panic!("Failed GET request to {}", confirmation_link));
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let client = server_postgres.postgres_pool.get().await.unwrap();
+    let row = client
+        .query_one(
+            "SELECT status FROM newsletter.subscription WHERE email=$1::TEXT",
+            &[&email_field],
+        )
+        .await
+        .expect("Failed to fetch saved subscription.");
+    let status: &str = row.get("status");
+    assert_eq!("confirmed", status);
+}
+
+#[tokio::test]
+async fn subscription_confirm_rejects_invalid_tokens() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    let test_cases = vec![
+        ("", 400, "missing token parameter"),
+        ("?token=short", 400, "malformed token"),
+        ("?token=aaaaaaaaaaaaaaaaaaaaaaaaa", 401, "unknown token"),
+    ];
+    for (query, expected_status, error_message) in test_cases {
+        // Act
+        let confirm_route = format!("{}/subscription/confirm{}", server_postgres.address, query);
+        let response = reqwest::get(&confirm_route)
+            .await
+            .unwrap_or_else(|_| panic!("Failed GET request to {}", confirm_route));
+        // Assert
+        assert_eq!(
+            expected_status,
+            response.status().as_u16(),
+            "Unexpected API response code when confirming with {}.",
+            error_message
+        );
+    }
+}
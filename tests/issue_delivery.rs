@@ -0,0 +1,94 @@
+mod common;
+
+use common::{
+    create_confirmed_subscriber, launch_http_server_with_admin, publish_newsletter,
+    test_delivery_worker_settings, wait_for_completed_issue,
+};
+use newsletter_rs::issue_delivery_worker::{run_worker_until_stopped, UnsubscribeLinkSettings};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn deliveries_are_retried_then_recorded_as_permanent_failures() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    create_confirmed_subscriber(&server_postgres, "delivered@drconopoima.com").await;
+    create_confirmed_subscriber(&server_postgres, "bouncing@drconopoima.com").await;
+    server_postgres.email_client.sent.lock().unwrap().clear();
+    server_postgres
+        .email_client
+        .failing_recipients
+        .lock()
+        .unwrap()
+        .push("bouncing@drconopoima.com".to_owned());
+    // Act
+    let issue_id = publish_newsletter(&server_postgres, "Retried issue").await;
+    let progress = wait_for_completed_issue(&server_postgres, &issue_id).await;
+    // Assert
+    assert_eq!(1, progress["delivered"]);
+    assert_eq!(1, progress["failed"]);
+    let client = server_postgres.postgres_pool.get().await.unwrap();
+    let failure = client
+        .query_one(
+            r#"
+                SELECT failures.n_retries, failures.last_error
+                FROM newsletter.issue_delivery_failures AS failures
+                JOIN newsletter.subscription AS subscription
+                    ON subscription.id = failures.subscriber_id
+                WHERE subscription.email = 'bouncing@drconopoima.com'
+            "#,
+            &[],
+        )
+        .await
+        .expect("Failed to fetch recorded delivery failure.");
+    let n_retries: i32 = failure.get("n_retries");
+    let last_error: &str = failure.get("last_error");
+    assert_eq!(test_delivery_worker_settings().maxretries, n_retries);
+    assert!(last_error.contains("bouncing@drconopoima.com"));
+    let pending = client
+        .query_one(
+            "SELECT count(*) AS pending FROM newsletter.issue_delivery_queue",
+            &[],
+        )
+        .await
+        .unwrap();
+    let pending: i64 = pending.get("pending");
+    assert_eq!(0, pending);
+}
+
+#[tokio::test]
+async fn concurrent_workers_deliver_each_email_once() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let recipients: Vec<String> = (0..20)
+        .map(|n| format!("concurrent_{}@drconopoima.com", n))
+        .collect();
+    for recipient in &recipients {
+        create_confirmed_subscriber(&server_postgres, recipient).await;
+    }
+    server_postgres.email_client.sent.lock().unwrap().clear();
+    // Additional workers compete with the one launched along with the servers
+    for _ in 0..3 {
+        std::mem::drop(tokio::spawn(run_worker_until_stopped(
+            Arc::new(server_postgres.postgres_pool.clone()),
+            server_postgres.email_client.clone(),
+            test_delivery_worker_settings(),
+            UnsubscribeLinkSettings {
+                base_url: server_postgres.address.to_owned(),
+                hmac_secret: server_postgres.hmac_secret.clone(),
+            },
+        )));
+    }
+    // Act
+    let issue_id = publish_newsletter(&server_postgres, "Concurrent issue").await;
+    let progress = wait_for_completed_issue(&server_postgres, &issue_id).await;
+    // Assert
+    assert_eq!(20, progress["delivered"]);
+    let sent = server_postgres.email_client.sent.lock().unwrap();
+    let mut deliveries: HashMap<&str, usize> = HashMap::new();
+    for message in sent.iter() {
+        *deliveries.entry(message.to.as_str()).or_default() += 1;
+    }
+    assert_eq!(recipients.len(), deliveries.len());
+    assert!(deliveries.values().all(|count| *count == 1));
+}
@@ -0,0 +1,432 @@
+use newsletter_rs::cli::{run_migrate_command, MigrateCommand};
+use newsletter_rs::configuration::{
+    get_configuration, DatabaseCreationSettings, DatabaseSettings, DriftPolicy, MigrationSettings,
+    PoolRecyclingMethod, PoolSettings, SslMode,
+};
+use newsletter_rs::postgres::{get_client, migrate_database, PostgresSetupError};
+use secrecy::SecretString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+// Copy of the migrations folder that tests can add scripts to
+fn migrations_folder_copy() -> PathBuf {
+    let folder = std::env::temp_dir().join(format!("newsletter-migrations-{}", Uuid::new_v4()));
+    fs::create_dir_all(&folder).unwrap();
+    for entry in fs::read_dir("migrations").unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, folder.join(path.file_name().unwrap())).unwrap();
+    }
+    folder
+}
+
+// Settings of a database that doesn't exist yet
+fn isolated_database_settings(folder: &Path) -> DatabaseSettings {
+    let mut configuration = get_configuration("main.yaml").expect("Failed to read configuration");
+    configuration.database.database = Some(Uuid::new_v4().simple().to_string());
+    configuration.database.migration = Some(MigrationSettings {
+        migrate: true,
+        folder: Some(folder.display().to_string()),
+        ondrift: DriftPolicy::Fail,
+    });
+    configuration.database
+}
+
+async fn run(command: MigrateCommand, folder: &Path, database: &str) -> (String, bool) {
+    let mut database_settings = isolated_database_settings(folder);
+    database_settings.database = Some(database.to_owned());
+    let outcome = run_migrate_command(&command, database_settings)
+        .await
+        .expect("Migrate command failed");
+    (outcome.report, outcome.success)
+}
+
+#[tokio::test]
+async fn migrate_up_applies_pending_scripts_once() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let database_settings = isolated_database_settings(&folder);
+    let database = database_settings.database.clone().unwrap();
+    let script_count = fs::read_dir(&folder).unwrap().count();
+    // Act
+    let (first_report, first_success) =
+        run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    let (second_report, _) = run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    // Assert
+    assert!(first_success);
+    assert!(first_report.starts_with(&format!("Applied {} migrations:", script_count)));
+    assert!(second_report.starts_with("Applied 0 migrations:"));
+    let (status_report, _) = run(MigrateCommand::Status, &folder, &database).await;
+    assert!(status_report.contains(&format!("Applied migrations: {}", script_count)));
+    assert!(status_report.contains("Pending migrations: 0"));
+    let (_, verified) = run(MigrateCommand::Verify, &folder, &database).await;
+    assert!(verified);
+}
+
+#[tokio::test]
+async fn dry_run_and_verify_report_new_scripts_without_applying_them() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let database_settings = isolated_database_settings(&folder);
+    let database = database_settings.database.clone().unwrap();
+    run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    fs::write(
+        folder.join("99990101000000_add_column.sql"),
+        "ALTER TABLE newsletter.subscription ADD COLUMN source TEXT;",
+    )
+    .unwrap();
+    // Act
+    let (dry_run_report, dry_run_success) =
+        run(MigrateCommand::Up { dry_run: true }, &folder, &database).await;
+    let (verify_report, verified) = run(MigrateCommand::Verify, &folder, &database).await;
+    // Assert
+    assert!(dry_run_success);
+    assert!(dry_run_report.contains("would apply 1 migrations"));
+    assert!(dry_run_report.contains("99990101000000_add_column.sql"));
+    assert!(!verified);
+    assert!(verify_report.contains("[pending]      99990101000000_add_column.sql"));
+    let (status_report, _) = run(MigrateCommand::Status, &folder, &database).await;
+    assert!(status_report.contains("Pending migrations: 1"));
+}
+
+#[tokio::test]
+async fn edited_migrations_block_up_until_repaired() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let database_settings = isolated_database_settings(&folder);
+    let database = database_settings.database.clone().unwrap();
+    run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    let edited_script = folder.join("20220305131000_postgresql_healthcheck_table.sql");
+    let mut contents = fs::read_to_string(&edited_script).unwrap();
+    contents.push_str("\n-- Reviewed comment\n");
+    fs::write(&edited_script, contents).unwrap();
+    // Act
+    let mut database_settings = isolated_database_settings(&folder);
+    database_settings.database = Some(database.to_owned());
+    let up_outcome =
+        run_migrate_command(&MigrateCommand::Up { dry_run: false }, database_settings).await;
+    let (status_report, _) = run(MigrateCommand::Status, &folder, &database).await;
+    let (repair_report, repaired) = run(MigrateCommand::Repair, &folder, &database).await;
+    // Assert
+    let error = up_outcome.unwrap_err();
+    match error.downcast_ref::<PostgresSetupError>() {
+        Some(PostgresSetupError::ChecksumDrift(filenames)) => assert_eq!(
+            &vec!["20220305131000_postgresql_healthcheck_table.sql".to_owned()],
+            filenames
+        ),
+        _ => panic!("Expected checksum drift, got '{:#}'", error),
+    }
+    assert!(status_report.contains("Applied migrations edited since they ran: 1"));
+    assert!(status_report.contains("Pending migrations: 0"));
+    assert!(repaired);
+    assert!(repair_report.starts_with("Repaired 1 migration checksums:"));
+    let (_, verified) = run(MigrateCommand::Verify, &folder, &database).await;
+    assert!(verified);
+}
+
+#[tokio::test]
+async fn edited_migrations_are_skipped_when_drift_only_warns() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let database_settings = isolated_database_settings(&folder);
+    let database = database_settings.database.clone().unwrap();
+    run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    fs::write(
+        folder.join("20220305131000_postgresql_healthcheck_table.sql"),
+        "SELECT 1;",
+    )
+    .unwrap();
+    let mut database_settings = isolated_database_settings(&folder);
+    database_settings.database = Some(database.to_owned());
+    database_settings.migration.as_mut().unwrap().ondrift = DriftPolicy::Warn;
+    // Act
+    let outcome = run_migrate_command(&MigrateCommand::Up { dry_run: false }, database_settings)
+        .await
+        .expect("Drift should only be logged");
+    // Assert
+    assert!(outcome.report.starts_with("Applied 0 migrations:"));
+}
+
+#[tokio::test]
+async fn concurrent_migrators_apply_each_script_once() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let database_settings = isolated_database_settings(&folder);
+    let database = database_settings.database.clone().unwrap();
+    run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    fs::write(
+        folder.join("99990101000000_create_table.sql"),
+        "CREATE TABLE newsletter.concurrent_probe(id INT);",
+    )
+    .unwrap();
+    // Act
+    let (first, second) = tokio::join!(
+        run(MigrateCommand::Up { dry_run: false }, &folder, &database),
+        run(MigrateCommand::Up { dry_run: false }, &folder, &database)
+    );
+    // Assert
+    let mut reports = [first.0, second.0];
+    reports.sort();
+    assert!(reports[0].starts_with("Applied 0 migrations:"));
+    assert!(reports[1].starts_with("Applied 1 migrations:"));
+    let (_, verified) = run(MigrateCommand::Verify, &folder, &database).await;
+    assert!(verified);
+}
+
+#[tokio::test]
+async fn failed_migration_is_rolled_back_and_stays_pending() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let database_settings = isolated_database_settings(&folder);
+    let database = database_settings.database.clone().unwrap();
+    run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    fs::write(
+        folder.join("99990101000000_failing.sql"),
+        "BEGIN;\nCREATE TABLE newsletter.rollback_probe(id INT);\nSELECT 1/0;\nCOMMIT;",
+    )
+    .unwrap();
+    let mut database_settings = isolated_database_settings(&folder);
+    database_settings.database = Some(database.to_owned());
+    // Act
+    let outcome =
+        run_migrate_command(&MigrateCommand::Up { dry_run: false }, database_settings).await;
+    // Assert
+    let error = format!("{:#}", outcome.unwrap_err());
+    assert!(
+        error.contains("Migration '99990101000000_failing.sql' failed"),
+        "{}",
+        error
+    );
+    let (status_report, _) = run(MigrateCommand::Status, &folder, &database).await;
+    assert!(status_report.contains("[pending]      99990101000000_failing.sql"));
+    fs::write(
+        folder.join("99990101000000_failing.sql"),
+        "CREATE TABLE newsletter.rollback_probe(id INT);",
+    )
+    .unwrap();
+    // Would fail on an existing table if the first attempt hadn't been rolled back
+    let (report, _) = run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    assert!(report.starts_with("Applied 1 migrations:"));
+}
+
+#[tokio::test]
+async fn no_transaction_scripts_can_create_indexes_concurrently() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let database_settings = isolated_database_settings(&folder);
+    let database = database_settings.database.clone().unwrap();
+    fs::write(
+        folder.join("99990101000000_index_concurrently.sql"),
+        "-- migrate:no-transaction\nCREATE INDEX CONCURRENTLY subscription_name_idx ON newsletter.subscription(name);",
+    )
+    .unwrap();
+    // Act
+    let (report, success) = run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    // Assert
+    assert!(success);
+    assert!(report.contains("99990101000000_index_concurrently.sql"));
+    let (_, verified) = run(MigrateCommand::Verify, &folder, &database).await;
+    assert!(verified);
+}
+
+#[tokio::test]
+async fn rollback_reverts_newer_migrations_with_their_down_scripts() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let database_settings = isolated_database_settings(&folder);
+    let database = database_settings.database.clone().unwrap();
+    for (version, table) in [
+        (99990101000000_i64, "first_probe"),
+        (99990102000000, "second_probe"),
+    ] {
+        fs::write(
+            folder.join(format!("{}_{}.up.sql", version, table)),
+            format!("CREATE TABLE newsletter.{}(id INT);", table),
+        )
+        .unwrap();
+        fs::write(
+            folder.join(format!("{}_{}.down.sql", version, table)),
+            format!("DROP TABLE newsletter.{};", table),
+        )
+        .unwrap();
+    }
+    run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    // Act
+    let (rollback_report, rolled_back) = run(
+        MigrateCommand::Rollback { to: 99990101000000 },
+        &folder,
+        &database,
+    )
+    .await;
+    // Assert
+    assert!(rolled_back);
+    assert!(rollback_report.starts_with("Rolled back 1 migrations:"));
+    assert!(rollback_report.contains("99990102000000_second_probe.up.sql"));
+    let (status_report, _) = run(MigrateCommand::Status, &folder, &database).await;
+    assert!(status_report.contains("[pending]      99990102000000_second_probe.up.sql"));
+    // Reapplying succeeds since the down script dropped the table
+    let (up_report, _) = run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    assert!(up_report.starts_with("Applied 1 migrations:"));
+}
+
+#[tokio::test]
+async fn rollback_past_forward_only_migrations_reverts_nothing() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let database_settings = isolated_database_settings(&folder);
+    let database = database_settings.database.clone().unwrap();
+    fs::write(
+        folder.join("99990101000000_probe.up.sql"),
+        "CREATE TABLE newsletter.probe(id INT);",
+    )
+    .unwrap();
+    fs::write(
+        folder.join("99990101000000_probe.down.sql"),
+        "DROP TABLE newsletter.probe;",
+    )
+    .unwrap();
+    run(MigrateCommand::Up { dry_run: false }, &folder, &database).await;
+    let mut database_settings = isolated_database_settings(&folder);
+    database_settings.database = Some(database.to_owned());
+    // Act
+    let outcome = run_migrate_command(&MigrateCommand::Rollback { to: 0 }, database_settings).await;
+    // Assert
+    let error = format!("{:#}", outcome.unwrap_err());
+    assert!(error.contains("is forward-only"), "{}", error);
+    let (_, verified) = run(MigrateCommand::Verify, &folder, &database).await;
+    assert!(verified);
+}
+
+#[tokio::test]
+async fn migrate_status_fails_readably_on_missing_database() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let database_settings = isolated_database_settings(&folder);
+    // Act
+    let outcome = run_migrate_command(&MigrateCommand::Status, database_settings).await;
+    // Assert
+    let error = format!("{:#}", outcome.unwrap_err());
+    assert!(error.contains("Failed to connect to database"), "{}", error);
+}
+
+#[tokio::test]
+async fn migrate_database_reports_unreachable_server_as_transient() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let mut database_settings = isolated_database_settings(&folder);
+    // Nothing listens on port 1
+    database_settings.port = 1;
+    // Act
+    let outcome = migrate_database(database_settings).await;
+    // Assert
+    match outcome {
+        Err(error @ PostgresSetupError::Connect { .. }) => {
+            assert!(error.is_transient());
+            assert!(!error.to_string().contains(":password@"));
+        }
+        Err(error) => panic!("Expected connection failure, got '{}'", error),
+        Ok(_) => panic!("Expected connection failure"),
+    }
+}
+
+#[tokio::test]
+async fn migrate_database_reports_rejected_credentials_as_permanent() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let mut database_settings = isolated_database_settings(&folder);
+    database_settings.password = Some(SecretString::from("not-the-password"));
+    // Act
+    let outcome = migrate_database(database_settings).await;
+    // Assert
+    match outcome {
+        Err(error @ PostgresSetupError::Connect { .. }) => assert!(!error.is_transient()),
+        Err(error) => panic!("Expected connection failure, got '{}'", error),
+        Ok(_) => panic!("Expected connection failure"),
+    }
+}
+
+#[tokio::test]
+async fn databases_with_quotes_in_their_name_are_created_with_options() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let mut database_settings = isolated_database_settings(&folder);
+    let database = format!("news\"let'ter {}", Uuid::new_v4().simple());
+    database_settings.database = Some(database.to_owned());
+    database_settings.creation = Some(DatabaseCreationSettings {
+        owner: Some(database_settings.username.to_owned()),
+        encoding: Some("UTF8".to_owned()),
+        locale: None,
+        template: Some("template0".to_owned()),
+    });
+    // Act
+    let outcome = run_migrate_command(&MigrateCommand::Up { dry_run: false }, database_settings)
+        .await
+        .expect("Failed to create database with quotes in its name");
+    // Assert
+    assert!(outcome.success);
+    let (_, verified) = run(MigrateCommand::Verify, &folder, &database).await;
+    assert!(verified);
+}
+
+#[tokio::test]
+async fn pool_settings_apply_to_migrated_database_sessions() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let mut database_settings = isolated_database_settings(&folder);
+    database_settings.pool = Some(PoolSettings {
+        maxsize: Some(2),
+        waittimeoutms: Some(1000),
+        recyclingmethod: PoolRecyclingMethod::Clean,
+        applicationname: Some("newsletter-rs-tests".to_owned()),
+        statementtimeoutms: Some(1500),
+        ..PoolSettings::default()
+    });
+    // Act
+    let pool = migrate_database(database_settings)
+        .await
+        .expect("Failed to migrate database");
+    let client = get_client(pool.clone()).await.unwrap();
+    // Assert
+    assert_eq!(2, pool.status().max_size);
+    let application_name: String = client
+        .query_one("SHOW application_name", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!("newsletter-rs-tests", application_name);
+    let statement_timeout: String = client
+        .query_one("SHOW statement_timeout", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!("1500ms", statement_timeout);
+}
+
+#[tokio::test]
+async fn prefer_ssl_mode_connects_with_or_without_server_tls() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let mut database_settings = isolated_database_settings(&folder);
+    database_settings.ssl.mode = Some(SslMode::Prefer);
+    // Act
+    let outcome = migrate_database(database_settings).await;
+    // Assert
+    assert!(outcome.is_ok());
+}
+
+#[tokio::test]
+async fn malformed_ca_certificates_fail_without_retrying() {
+    // Arrange
+    let folder = migrations_folder_copy();
+    let mut database_settings = isolated_database_settings(&folder);
+    database_settings.ssl.mode = Some(SslMode::VerifyFull);
+    database_settings.ssl.cacertificates =
+        Some("-----BEGIN CERTIFICATE-----\nM***EXAMPLE***\n-----END CERTIFICATE-----\n".to_owned());
+    // Act
+    let error = migrate_database(database_settings)
+        .await
+        .expect_err("Connected with a malformed CA certificate");
+    // Assert
+    assert!(matches!(error, PostgresSetupError::Configuration(_)));
+    assert!(!error.is_transient());
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use common::{
+    create_confirmed_subscriber, launch_http_server_with_admin, post_subscription,
+    wait_for_completed_issue, Body,
+};
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers_only() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    create_confirmed_subscriber(&server_postgres, "confirmed_one@drconopoima.com").await;
+    create_confirmed_subscriber(&server_postgres, "confirmed_two@drconopoima.com").await;
+    let pending = Body {
+        email: "still_pending@drconopoima.com".to_owned(),
+        name: "Jane Doe".to_owned(),
+    };
+    assert_eq!(
+        200,
+        post_subscription(&server_postgres, &pending)
+            .await
+            .status()
+            .as_u16()
+    );
+    server_postgres.email_client.sent.lock().unwrap().clear();
+    let newsletters_route = format!(
+        "{}/admin/newsletters",
+        server_postgres.admin_address.as_ref().unwrap()
+    );
+    // Act
+    let response = server_postgres
+        .admin_client
+        .post(&newsletters_route)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+        }))
+        .send()
+        .await
+        .unwrap_or_else(|_| panic!("Failed POST request to {}", newsletters_route));
+    // Assert
+    assert_eq!(202, response.status().as_u16());
+    let published: serde_json::Value = response.json().await.unwrap();
+    let issue_id = published["issue_id"].as_str().unwrap().to_owned();
+    let progress = wait_for_completed_issue(&server_postgres, &issue_id).await;
+    assert_eq!(2, progress["total_recipients"]);
+    assert_eq!(2, progress["delivered"]);
+    assert_eq!(0, progress["failed"]);
+    let sent = server_postgres.email_client.sent.lock().unwrap();
+    let mut recipients: Vec<&str> = sent.iter().map(|message| message.to.as_str()).collect();
+    recipients.sort();
+    assert_eq!(
+        vec![
+            "confirmed_one@drconopoima.com",
+            "confirmed_two@drconopoima.com"
+        ],
+        recipients
+    );
+    assert!(sent
+        .iter()
+        .all(|message| message.subject == "Newsletter title"));
+}
+
+#[tokio::test]
+async fn newsletters_400_invalid_issue() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let newsletters_route = format!(
+        "{}/admin/newsletters",
+        server_postgres.admin_address.as_ref().unwrap()
+    );
+    let test_cases = vec![
+        (
+            serde_json::json!({"html_content": "<p>body</p>", "text_content": "body"}),
+            "missing title",
+        ),
+        (
+            serde_json::json!({"title": "Title", "text_content": "body"}),
+            "missing html content",
+        ),
+        (
+            serde_json::json!({"title": " ", "html_content": "<p>body</p>", "text_content": "body"}),
+            "blank title",
+        ),
+        (
+            serde_json::json!({"title": "Title", "html_content": "<p>body</p>", "text_content": ""}),
+            "empty text content",
+        ),
+    ];
+    for (invalid_body, error_message) in test_cases {
+        // Act
+        let response = server_postgres
+            .admin_client
+            .post(&newsletters_route)
+            .json(&invalid_body)
+            .send()
+            .await
+            .unwrap_or_else(|_| panic!("Failed POST request to {}", newsletters_route));
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "Expected API failure response code to be 400 Bad Request when body payload was {}.",
+            error_message
+        );
+    }
+}
+
+#[tokio::test]
+async fn newsletters_are_not_served_on_public_listener() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/newsletters", server_postgres.address))
+        .json(&serde_json::json!({
+            "title": "Title",
+            "html_content": "<p>body</p>",
+            "text_content": "body",
+        }))
+        .send()
+        .await
+        .expect("Failed POST request to public listener");
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn newsletter_progress_404_unknown_issue() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let progress_route = format!(
+        "{}/admin/newsletters/{}",
+        server_postgres.admin_address.as_ref().unwrap(),
+        uuid::Uuid::new_v4()
+    );
+    // Act
+    let response = server_postgres
+        .admin_client
+        .get(&progress_route)
+        .send()
+        .await
+        .unwrap_or_else(|_| panic!("Failed GET request to {}", progress_route));
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
@@ -0,0 +1,104 @@
+use newsletter_rs::configuration::{
+    get_configuration, DatabaseSettings, DriftPolicy, MigrationSettings, PostgresHostSettings,
+};
+use newsletter_rs::postgres::{
+    generate_replica_pool, migrate_database, PostgresRouter, PostgresTarget,
+};
+use newsletter_rs::readiness::{probe_readiness, STATUS_PASS, STATUS_WARN};
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Migrated database, with the primary itself standing in for a replica unless told otherwise
+async fn router_with_replica(replica_port: Option<u16>) -> PostgresRouter {
+    let mut configuration = get_configuration("main.yaml").expect("Failed to read configuration");
+    configuration.database.database = Some(Uuid::new_v4().simple().to_string());
+    configuration.database.migration = Some(MigrationSettings {
+        migrate: true,
+        folder: None,
+        ondrift: DriftPolicy::Fail,
+    });
+    let database_settings: DatabaseSettings = configuration.database;
+    let replicas = replica_port.map(|port| {
+        vec![PostgresHostSettings {
+            host: database_settings.host.to_owned(),
+            port,
+        }]
+    });
+    let database_settings = DatabaseSettings {
+        replicas,
+        ..database_settings
+    };
+    let replica_pool =
+        generate_replica_pool(&database_settings).expect("Failed to build replicas pool");
+    let primary_pool = migrate_database(database_settings)
+        .await
+        .expect("Failed to migrate database");
+    PostgresRouter::new(primary_pool, replica_pool)
+}
+
+fn postgres_port() -> u16 {
+    get_configuration("main.yaml")
+        .expect("Failed to read configuration")
+        .database
+        .port
+}
+
+#[tokio::test]
+async fn reads_go_to_healthy_replicas() {
+    // Arrange
+    let router = router_with_replica(Some(postgres_port())).await;
+    // Act
+    let (postgres_client, target) = router.get_read_client().await.unwrap();
+    // Assert
+    assert_eq!(PostgresTarget::Replica, target);
+    let row = postgres_client.query_one("SELECT 1", &[]).await.unwrap();
+    assert_eq!(1, row.get::<_, i32>(0));
+}
+
+#[tokio::test]
+async fn reads_fall_back_to_primary_without_replicas() {
+    // Arrange
+    let router = router_with_replica(None).await;
+    // Act
+    let (_, target) = router.get_read_client().await.unwrap();
+    // Assert
+    assert_eq!(PostgresTarget::Primary, target);
+    assert!(!router.is_replica_healthy());
+}
+
+#[tokio::test]
+async fn unreachable_replicas_fall_back_to_primary_and_are_reported() {
+    // Arrange
+    let router = Arc::new(router_with_replica(Some(1)).await);
+    // Act
+    let (_, target) = router.get_read_client().await.unwrap();
+    let health = probe_readiness(router.clone()).await;
+    // Assert
+    assert_eq!(PostgresTarget::Primary, target);
+    assert!(!router.is_replica_healthy());
+    assert_eq!(STATUS_WARN, health.status);
+    assert_eq!(STATUS_PASS, health.checks.postgres_read.status);
+    assert_eq!(
+        Some("primary"),
+        health.checks.postgres_read.target.as_deref()
+    );
+    assert_eq!(STATUS_PASS, health.checks.postgres_write.status);
+}
+
+#[tokio::test]
+async fn readiness_probe_restores_recovered_replicas() {
+    // Arrange
+    let router = Arc::new(router_with_replica(Some(postgres_port())).await);
+    router.set_replica_healthy(false);
+    // Act
+    let health = probe_readiness(router.clone()).await;
+    // Assert
+    assert!(router.is_replica_healthy());
+    assert_eq!(STATUS_PASS, health.status);
+    assert_eq!(
+        Some("replica"),
+        health.checks.postgres_read.target.as_deref()
+    );
+    let (_, target) = router.get_read_client().await.unwrap();
+    assert_eq!(PostgresTarget::Replica, target);
+}
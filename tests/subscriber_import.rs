@@ -0,0 +1,154 @@
+mod common;
+
+use common::{create_confirmed_subscriber, launch_http_server_with_admin, ServerPostgres};
+
+fn admin_route(server_postgres: &ServerPostgres, route: &str) -> String {
+    format!(
+        "{}{}",
+        server_postgres.admin_address.as_ref().unwrap(),
+        route
+    )
+}
+
+async fn post_import(server_postgres: &ServerPostgres, csv: &str) -> reqwest::Response {
+    server_postgres
+        .admin_client
+        .post(admin_route(server_postgres, "/admin/subscribers/import"))
+        .header(reqwest::header::CONTENT_TYPE, "text/csv")
+        .body(csv.to_owned())
+        .send()
+        .await
+        .expect("Failed POST request to import subscribers")
+}
+
+#[tokio::test]
+async fn import_merges_valid_rows_and_reports_rejections() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    create_confirmed_subscriber(&server_postgres, "existing@drconopoima.com").await;
+    let csv = "\u{feff}Name,Email,Source\n\
+        Jane Doe,Jane@drconopoima.com,old provider\n\
+        \"Smith, John\",john@drconopoima.com,old provider\n\
+        Nobody,not-an-email,old provider\n\
+        <script>,script@drconopoima.com,old provider\n\
+        Again,existing@drconopoima.com,old provider\n\
+        Twice,jane@drconopoima.com,old provider\n\
+        Short row\n";
+    // Act
+    let response = post_import(&server_postgres, csv).await;
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(2, report["imported"]);
+    let rejected: Vec<(u64, &str)> = report["rejected"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|rejection| {
+            assert!(!rejection["reason"].as_str().unwrap().is_empty());
+            (
+                rejection["row"].as_u64().unwrap(),
+                rejection["email"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            (4, "not-an-email"),
+            (5, "script@drconopoima.com"),
+            (6, "existing@drconopoima.com"),
+            (7, "jane@drconopoima.com"),
+            (8, ""),
+        ],
+        rejected
+    );
+    let listing: serde_json::Value = server_postgres
+        .admin_client
+        .get(admin_route(
+            &server_postgres,
+            "/admin/subscribers?status=confirmed",
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let subscribers = listing["subscribers"].as_array().unwrap();
+    assert_eq!(3, subscribers.len());
+    assert_eq!("jane@drconopoima.com", subscribers[1]["email"]);
+    assert_eq!("Smith, John", subscribers[2]["name"]);
+}
+
+#[tokio::test]
+async fn import_reports_mixed_case_duplicates_as_earlier_rows() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let csv = "email,name\n\
+        Foo@drconopoima.com,Jane Doe\n\
+        foo@DRCONOPOIMA.com,John Doe\n";
+    // Act
+    let response = post_import(&server_postgres, csv).await;
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(1, report["imported"]);
+    let rejected = report["rejected"].as_array().unwrap();
+    assert_eq!(1, rejected.len());
+    assert_eq!(3, rejected[0]["row"]);
+    assert!(rejected[0]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("appears on an earlier row"));
+}
+
+#[tokio::test]
+async fn import_400_invalid_upload() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let test_cases = vec![
+        ("", "empty upload"),
+        ("email\njane@drconopoima.com\n", "missing name column"),
+        (
+            "name,mail\nJane,jane@drconopoima.com\n",
+            "missing email column",
+        ),
+    ];
+    for (csv, description) in test_cases {
+        // Act
+        let response = post_import(&server_postgres, csv).await;
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "Expected 400 Bad Request with {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn import_requires_admin_session() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let created: serde_json::Value = server_postgres
+        .admin_client
+        .post(admin_route(&server_postgres, "/admin/api-keys"))
+        .json(&serde_json::json!({"name": "crm", "scopes": ["subscribers:read"]}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    // Act
+    let response = reqwest::Client::new()
+        .post(admin_route(&server_postgres, "/admin/subscribers/import"))
+        .bearer_auth(created["api_key"].as_str().unwrap())
+        .body("email,name\njane@drconopoima.com,Jane Doe\n")
+        .send()
+        .await
+        .unwrap();
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
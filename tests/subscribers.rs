@@ -0,0 +1,173 @@
+mod common;
+
+use common::{
+    create_confirmed_subscriber, launch_http_server_with_admin, post_subscription, Body,
+    ServerPostgres,
+};
+
+fn admin_route(server_postgres: &ServerPostgres, route: &str) -> String {
+    format!(
+        "{}{}",
+        server_postgres.admin_address.as_ref().unwrap(),
+        route
+    )
+}
+
+async fn subscribe(server_postgres: &ServerPostgres, email: &str, name: &str) {
+    let body = Body {
+        email: email.to_owned(),
+        name: name.to_owned(),
+    };
+    assert_eq!(
+        200,
+        post_subscription(server_postgres, &body)
+            .await
+            .status()
+            .as_u16()
+    );
+}
+
+async fn list_subscribers(server_postgres: &ServerPostgres, query: &str) -> reqwest::Response {
+    server_postgres
+        .admin_client
+        .get(admin_route(
+            server_postgres,
+            &format!("/admin/subscribers?{}", query),
+        ))
+        .send()
+        .await
+        .expect("Failed GET request to list subscribers")
+}
+
+fn emails(page: &serde_json::Value) -> Vec<String> {
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|subscriber| subscriber["email"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_by_cursor() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    for index in 0..5 {
+        subscribe(
+            &server_postgres,
+            &format!("page_{}@drconopoima.com", index),
+            "Jane Doe",
+        )
+        .await;
+    }
+    let mut listed = Vec::new();
+    let mut query = "limit=2".to_owned();
+    // Act
+    for _ in 0..3 {
+        let response = list_subscribers(&server_postgres, &query).await;
+        assert_eq!(200, response.status().as_u16());
+        let page: serde_json::Value = response.json().await.unwrap();
+        listed.extend(emails(&page));
+        match page["next_cursor"].as_str() {
+            Some(cursor) => query = format!("limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+    // Assert
+    let expected: Vec<String> = (0..5)
+        .map(|index| format!("page_{}@drconopoima.com", index))
+        .collect();
+    assert_eq!(expected, listed);
+}
+
+#[tokio::test]
+async fn subscribers_are_filtered() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    create_confirmed_subscriber(&server_postgres, "confirmed@drconopoima.com").await;
+    subscribe(&server_postgres, "Pending@Example.com", "John Smith").await;
+    let test_cases = vec![
+        ("status=confirmed", vec!["confirmed@drconopoima.com"]),
+        ("status=pending_confirmation", vec!["pending@example.com"]),
+        ("email=example.COM", vec!["pending@example.com"]),
+        ("name=smith", vec!["pending@example.com"]),
+        ("subscribed_before=2000-01-01T00:00:00Z", vec![]),
+        (
+            "subscribed_after=2000-01-01T00:00:00Z",
+            vec!["confirmed@drconopoima.com", "pending@example.com"],
+        ),
+    ];
+    for (query, expected) in test_cases {
+        // Act
+        let response = list_subscribers(&server_postgres, query).await;
+        // Assert
+        assert_eq!(200, response.status().as_u16(), "Query {}", query);
+        let page: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(expected, emails(&page), "Query {}", query);
+        assert!(page["next_cursor"].is_null());
+    }
+}
+
+#[tokio::test]
+async fn list_subscribers_400_invalid_query() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let test_cases = vec![
+        ("status=deleted", "unknown status"),
+        ("limit=0", "limit below range"),
+        ("limit=1000", "limit above range"),
+        ("subscribed_after=yesterday", "non RFC 3339 date"),
+        ("cursor=not-a-uuid", "malformed cursor"),
+    ];
+    for (query, description) in test_cases {
+        // Act
+        let response = list_subscribers(&server_postgres, query).await;
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "Expected 400 Bad Request with {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn list_subscribers_requires_subscribers_read_scope() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let mut api_keys = Vec::new();
+    for scope in ["subscribers:read", "newsletters:send"] {
+        let created: serde_json::Value = server_postgres
+            .admin_client
+            .post(admin_route(&server_postgres, "/admin/api-keys"))
+            .json(&serde_json::json!({"name": "crm", "scopes": [scope]}))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        api_keys.push(created["api_key"].as_str().unwrap().to_owned());
+    }
+    let route = admin_route(&server_postgres, "/admin/subscribers");
+    let client = reqwest::Client::new();
+    // Act
+    let granted = client
+        .get(&route)
+        .bearer_auth(&api_keys[0])
+        .send()
+        .await
+        .unwrap();
+    let forbidden = client
+        .get(&route)
+        .bearer_auth(&api_keys[1])
+        .send()
+        .await
+        .unwrap();
+    let anonymous = client.get(&route).send().await.unwrap();
+    // Assert
+    assert_eq!(200, granted.status().as_u16());
+    assert_eq!(403, forbidden.status().as_u16());
+    assert_eq!(401, anonymous.status().as_u16());
+}
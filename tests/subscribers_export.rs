@@ -0,0 +1,127 @@
+mod common;
+
+use common::{create_confirmed_subscriber, launch_http_server_with_admin, ServerPostgres};
+
+fn admin_route(server_postgres: &ServerPostgres, route: &str) -> String {
+    format!(
+        "{}{}",
+        server_postgres.admin_address.as_ref().unwrap(),
+        route
+    )
+}
+
+async fn get_export(server_postgres: &ServerPostgres, query: &str) -> reqwest::Response {
+    server_postgres
+        .admin_client
+        .get(admin_route(
+            server_postgres,
+            &format!("/admin/subscribers/export?{}", query),
+        ))
+        .send()
+        .await
+        .expect("Failed GET request to export subscribers")
+}
+
+// Import more subscribers than a single cursor fetch returns
+async fn import_subscribers(server_postgres: &ServerPostgres, count: usize) {
+    let mut csv = "email,name\n".to_owned();
+    for index in 0..count {
+        csv.push_str(&format!(
+            "imported_{}@drconopoima.com,\"Doe, Jane {}\"\n",
+            index, index
+        ));
+    }
+    let response = server_postgres
+        .admin_client
+        .post(admin_route(server_postgres, "/admin/subscribers/import"))
+        .body(csv)
+        .send()
+        .await
+        .expect("Failed POST request to import subscribers");
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn csv_export_streams_every_matching_subscriber() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    import_subscribers(&server_postgres, 2500).await;
+    // Act
+    let response = get_export(&server_postgres, "format=csv&status=confirmed").await;
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "text/csv; charset=utf-8",
+        response.headers()[reqwest::header::CONTENT_TYPE]
+    );
+    let body = response.text().await.unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(2501, lines.len());
+    assert_eq!("id,email,name,status,subscription_date", lines[0]);
+    assert!(lines[1].contains(",imported_0@drconopoima.com,\"Doe, Jane 0\",confirmed,"));
+    assert!(lines[2500].contains(",imported_2499@drconopoima.com,"));
+}
+
+#[tokio::test]
+async fn jsonl_export_applies_filters() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    import_subscribers(&server_postgres, 3).await;
+    create_confirmed_subscriber(&server_postgres, "other@example.com").await;
+    // Act
+    let response = get_export(&server_postgres, "format=jsonl&email=example.com").await;
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body = response.text().await.unwrap();
+    let subscribers: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(1, subscribers.len());
+    assert_eq!("other@example.com", subscribers[0]["email"]);
+    assert_eq!("confirmed", subscribers[0]["status"]);
+    assert!(subscribers[0]["subscription_date"].is_string());
+}
+
+#[tokio::test]
+async fn exports_release_their_connection() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    import_subscribers(&server_postgres, 10).await;
+    for _ in 0..4 {
+        // Act
+        let response = get_export(&server_postgres, "format=csv").await;
+        // Assert
+        assert_eq!(200, response.status().as_u16());
+        assert_eq!(11, response.text().await.unwrap().lines().count());
+    }
+    let response = server_postgres
+        .admin_client
+        .get(admin_route(&server_postgres, "/admin/subscribers"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn export_400_invalid_query() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    let test_cases = vec![
+        ("format=xml", "unknown format"),
+        ("status=deleted", "unknown status"),
+        ("subscribed_before=tomorrow", "non RFC 3339 date"),
+    ];
+    for (query, description) in test_cases {
+        // Act
+        let response = get_export(&server_postgres, query).await;
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "Expected 400 Bad Request with {}.",
+            description
+        );
+    }
+}
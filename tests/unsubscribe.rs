@@ -0,0 +1,232 @@
+mod common;
+
+use common::{
+    create_confirmed_subscriber, launch_http_server, launch_http_server_with_admin,
+    post_subscription, publish_newsletter, wait_for_completed_issue, Body, ServerPostgres,
+};
+
+async fn subscriber_status(server_postgres: &ServerPostgres, email: &str) -> String {
+    let client = server_postgres.postgres_pool.get().await.unwrap();
+    let row = client
+        .query_one(
+            "SELECT status FROM newsletter.subscription WHERE email = $1",
+            &[&email],
+        )
+        .await
+        .expect("Failed to fetch subscriber status.");
+    row.get("status")
+}
+
+#[tokio::test]
+async fn unsubscribe_one_click_post_unsubscribes_subscriber() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    create_confirmed_subscriber(&server_postgres, "leaving@drconopoima.com").await;
+    let unsubscribe_link = server_postgres.email_client.last_unsubscribe_link();
+    // Act
+    let response = reqwest::Client::new()
+        .post(&unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap_or_else(|_| panic!("Failed POST request to {}", unsubscribe_link));
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "unsubscribed",
+        subscriber_status(&server_postgres, "leaving@drconopoima.com").await
+    );
+    let client = server_postgres.postgres_pool.get().await.unwrap();
+    let subscribers = client
+        .query_one("SELECT count(*) AS total FROM newsletter.subscription", &[])
+        .await
+        .unwrap();
+    let subscribers: i64 = subscribers.get("total");
+    assert_eq!(
+        1, subscribers,
+        "Unsubscribing must not delete the subscriber"
+    );
+}
+
+#[tokio::test]
+async fn unsubscribe_is_idempotent() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    create_confirmed_subscriber(&server_postgres, "leaving@drconopoima.com").await;
+    let unsubscribe_link = server_postgres.email_client.last_unsubscribe_link();
+    for _ in 0..2 {
+        // Act
+        let response = reqwest::Client::new()
+            .post(&unsubscribe_link)
+            .send()
+            .await
+            .unwrap_or_else(|_| panic!("Failed POST request to {}", unsubscribe_link));
+        // Assert
+        assert_eq!(200, response.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_can_subscribe_again() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    create_confirmed_subscriber(&server_postgres, "returning@drconopoima.com").await;
+    let unsubscribe_link = server_postgres.email_client.last_unsubscribe_link();
+    reqwest::Client::new()
+        .post(&unsubscribe_link)
+        .send()
+        .await
+        .unwrap_or_else(|_| panic!("Failed POST request to {}", unsubscribe_link));
+    let body = Body {
+        email: "Returning@drconopoima.com".to_owned(),
+        name: "Jane Doe".to_owned(),
+    };
+    // Act
+    let response = post_subscription(&server_postgres, &body).await;
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "pending_confirmation",
+        subscriber_status(&server_postgres, "returning@drconopoima.com").await
+    );
+    // Act
+    let confirmation_link = server_postgres.email_client.last_confirmation_link();
+    let response = reqwest::get(&confirmation_link)
+        .await
+        .unwrap_or_else(|_| panic!("Failed GET request to {}", confirmation_link));
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        "confirmed",
+        subscriber_status(&server_postgres, "returning@drconopoima.com").await
+    );
+    let client = server_postgres.postgres_pool.get().await.unwrap();
+    let row = client
+        .query_one(
+            "SELECT unsubscribed_at IS NULL AS cleared FROM newsletter.subscription WHERE email = $1",
+            &[&"returning@drconopoima.com"],
+        )
+        .await
+        .unwrap();
+    assert!(row.get::<_, bool>("cleared"));
+    // Act
+    let response = post_subscription(&server_postgres, &body).await;
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn unsubscribe_link_get_renders_confirmation_without_unsubscribing() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    create_confirmed_subscriber(&server_postgres, "staying@drconopoima.com").await;
+    let unsubscribe_link = server_postgres.email_client.last_unsubscribe_link();
+    // Act
+    let response = reqwest::get(&unsubscribe_link)
+        .await
+        .unwrap_or_else(|_| panic!("Failed GET request to {}", unsubscribe_link));
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"method="post""#));
+    assert!(page.contains(&format!(r#"action="{}""#, unsubscribe_link)));
+    assert_eq!(
+        "confirmed",
+        subscriber_status(&server_postgres, "staying@drconopoima.com").await
+    );
+}
+
+#[tokio::test]
+async fn unsubscribe_rejects_invalid_tokens() {
+    // Arrange
+    let server_postgres = launch_http_server().await;
+    create_confirmed_subscriber(&server_postgres, "staying@drconopoima.com").await;
+    let unsubscribe_link = server_postgres.email_client.last_unsubscribe_link();
+    let (_, token) = unsubscribe_link.split_once("token=").unwrap();
+    let (subscriber_id, signature) = token.split_once('.').unwrap();
+    let forged_signature = format!("{}.{}", subscriber_id, "0".repeat(signature.len()));
+    let test_cases = vec![
+        (forged_signature.as_str(), 401, "forged signature"),
+        ("not-a-token", 400, "malformed token"),
+        ("", 400, "empty token"),
+    ];
+    for (token, status, description) in test_cases {
+        let route = format!(
+            "{}/subscription/unsubscribe?token={}",
+            server_postgres.address, token
+        );
+        // Act
+        let response = reqwest::Client::new()
+            .post(&route)
+            .send()
+            .await
+            .unwrap_or_else(|_| panic!("Failed POST request to {}", route));
+        // Assert
+        assert_eq!(
+            status,
+            response.status().as_u16(),
+            "Unexpected response code for {}.",
+            description
+        );
+    }
+    assert_eq!(
+        "confirmed",
+        subscriber_status(&server_postgres, "staying@drconopoima.com").await
+    );
+}
+
+#[tokio::test]
+async fn newsletter_emails_carry_recipient_unsubscribe_link() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    create_confirmed_subscriber(&server_postgres, "reader@drconopoima.com").await;
+    let confirmation_unsubscribe_link = server_postgres.email_client.last_unsubscribe_link();
+    server_postgres.email_client.sent.lock().unwrap().clear();
+    // Act
+    let issue_id = publish_newsletter(&server_postgres, "Issue with unsubscribe link").await;
+    wait_for_completed_issue(&server_postgres, &issue_id).await;
+    // Assert
+    let sent = server_postgres.email_client.sent.lock().unwrap();
+    let message = sent.first().expect("Newsletter issue was not delivered");
+    assert_eq!(confirmation_unsubscribe_link, message.unsubscribe_link);
+    let headers = message.list_unsubscribe_headers();
+    assert_eq!(
+        (
+            "List-Unsubscribe",
+            format!("<{}>", message.unsubscribe_link)
+        ),
+        headers[0]
+    );
+    assert_eq!(
+        (
+            "List-Unsubscribe-Post",
+            "List-Unsubscribe=One-Click".to_owned()
+        ),
+        headers[1]
+    );
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_are_not_delivered_newsletters() {
+    // Arrange
+    let server_postgres = launch_http_server_with_admin().await;
+    create_confirmed_subscriber(&server_postgres, "leaving@drconopoima.com").await;
+    let unsubscribe_link = server_postgres.email_client.last_unsubscribe_link();
+    create_confirmed_subscriber(&server_postgres, "staying@drconopoima.com").await;
+    let response = reqwest::Client::new()
+        .post(&unsubscribe_link)
+        .send()
+        .await
+        .unwrap_or_else(|_| panic!("Failed POST request to {}", unsubscribe_link));
+    assert_eq!(200, response.status().as_u16());
+    server_postgres.email_client.sent.lock().unwrap().clear();
+    // Act
+    let issue_id = publish_newsletter(&server_postgres, "Issue after unsubscription").await;
+    let progress = wait_for_completed_issue(&server_postgres, &issue_id).await;
+    // Assert
+    assert_eq!(1, progress["total_recipients"]);
+    let sent = server_postgres.email_client.sent.lock().unwrap();
+    let recipients: Vec<&str> = sent.iter().map(|message| message.to.as_str()).collect();
+    assert_eq!(vec!["staying@drconopoima.com"], recipients);
+}